@@ -0,0 +1,52 @@
+//! Captures the versions of the `hdk-rs` crates this binary is built against,
+//! so `hdk versions` can report them.
+
+use std::path::Path;
+
+const HDK_CRATES: &[&str] = &[
+    "hdk-archive",
+    "hdk-comp",
+    "hdk-firmware",
+    "hdk-sdat",
+    "hdk-secure",
+];
+
+fn main() {
+    let lock_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock_path.display());
+
+    let lock = std::fs::read_to_string(&lock_path).unwrap_or_default();
+
+    for name in HDK_CRATES {
+        let version = locked_version(&lock, name).unwrap_or_else(|| "unknown".to_string());
+        let var = name.to_uppercase().replace('-', "_");
+        println!("cargo:rustc-env={var}_VERSION={version}");
+    }
+}
+
+/// Finds a package in `Cargo.lock`, returning its version and, for git
+/// dependencies, the short commit hash it's pinned to.
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|block| field(block, "name") == Some(name))?;
+
+    let version = field(package, "version")?;
+    let commit = field(package, "source")
+        .filter(|source| source.starts_with("git+"))
+        .and_then(|source| source.rsplit_once('#'))
+        .map(|(_, commit)| &commit[..commit.len().min(7)]);
+
+    Some(commit.map_or_else(
+        || version.to_string(),
+        |commit| format!("{version} (git {commit})"),
+    ))
+}
+
+/// Reads a `key = "value"` line from a `Cargo.lock` package block.
+fn field<'a>(block: &'a str, key: &str) -> Option<&'a str> {
+    block.lines().find_map(|line| {
+        let (k, v) = line.split_once(" = ")?;
+        (k.trim() == key).then(|| v.trim().trim_matches('"'))
+    })
+}
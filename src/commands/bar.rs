@@ -1,157 +1,493 @@
-use std::path::Path;
-
 use crate::{
-    commands::{Execute, IOArgs, common},
+    commands::{
+        AppendArgs, CreateArgs, Execute, ExtractArgs, IArg, RemoveArgs, common,
+        common::{AppendEntry, InputSource},
+        manifest::Manifest,
+        summary,
+    },
+    error::CliError,
     keys::{BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY},
-    magic,
 };
-use binrw::{BinRead, Endian};
-use clap::Subcommand;
+use binrw::Endian;
+use clap::{Args, Subcommand};
 use hdk_archive::{
-    bar::{builder::BarBuilder, structs::BarArchive},
-    structs::{ArchiveFlags, ArchiveFlagsValue},
+    bar::builder::BarBuilder,
+    structs::{ArchiveFlags, ArchiveFlagsValue, CompressionType, Entry},
 };
+use hdk_secure::hash::AfsHash;
+use smallvec::SmallVec;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 #[derive(Subcommand, Debug)]
 pub enum Bar {
     /// Create a BAR archive
     #[clap(alias = "c")]
-    Create(IOArgs),
+    Create(BarCreateArgs),
     /// Extract a BAR archive
     #[clap(alias = "x")]
-    Extract(IOArgs),
+    Extract(ExtractArgs),
+    /// Remove entries from a BAR archive, writing the result to a new file
+    #[clap(alias = "rm")]
+    Remove(RemoveArgs),
+    /// Add files to a BAR archive, writing the result to a new file
+    #[clap(alias = "a")]
+    Append(AppendArgs),
+    /// Print the header of a BAR archive and a summary of its entries
+    #[clap(alias = "i")]
+    Inspect(IArg),
 }
 
-impl Execute for Bar {
-    fn execute(self) {
-        let result = match self {
-            Self::Create(args) => Self::create(&args.input, &args.output),
-            Self::Extract(args) => Self::extract(&args.input, &args.output),
-        };
+#[derive(Args, Debug)]
+pub struct BarCreateArgs {
+    #[clap(flatten)]
+    pub create: CreateArgs,
 
-        if let Err(e) = result {
-            eprintln!("Error: {e}");
+    /// Write the original file names to `<output>.names` next to the archive,
+    /// so `bar extract` can restore them
+    #[clap(long, default_value_t = false)]
+    pub write_names: bool,
+}
+
+impl Execute for Bar {
+    fn execute(self) -> Result<(), CliError> {
+        match self {
+            Self::Create(args) => Self::create(&args.create, args.write_names),
+            Self::Extract(args) => {
+                for mut extract in args.each_input()? {
+                    common::use_sidecar_names(&mut extract);
+                    Self::extract(&extract)?;
+                }
+                Ok(())
+            }
+            Self::Remove(args) => Self::remove(&args),
+            Self::Append(args) => Self::append(&args),
+            Self::Inspect(args) => Self::inspect(&args.input),
         }
     }
 }
 
 impl Bar {
-    pub fn create(input: &Path, output: &Path) -> Result<(), String> {
+    pub fn create(args: &CreateArgs, write_names: bool) -> Result<(), CliError> {
+        let start = std::time::Instant::now();
+        let output = args.output.as_path();
+
+        let manifest = Manifest::from_args(args)?;
+
+        // Use the manifest's timestamp, or the input directory's `.time` file if it has one.
+        let mut timestamp = match (manifest.as_ref().and_then(|m| m.timestamp), &args.input) {
+            (Some(timestamp), _) => Some(timestamp),
+            (None, Some(input)) => common::read_time_file(input)?,
+            (None, None) => None,
+        };
+        if timestamp.is_none() && args.reproducibility.reproducible {
+            timestamp = Some(common::reproducible_timestamp()?);
+        }
+
+        let mut files = match &manifest {
+            Some(manifest) => manifest.files(),
+            None => common::collect_create_inputs(args)?,
+        };
+
+        // Sort ascending by signed AfsHash value
+        // This ensures they're written in the same order as the input files.
+        // A manifest's order is kept as-is.
+        if manifest.is_none() {
+            files.sort_by_key(|(_, _, a_hash)| a_hash.0);
+        }
+        common::check_hash_collisions(&mut files, args.strict)?;
+
+        if args.dry_run {
+            common::preview_create(
+                files
+                    .iter()
+                    .map(|(_, rel_path, hash)| (rel_path.as_path(), *hash)),
+                output,
+            );
+            return Ok(());
+        }
+
+        // The archive itself only keeps hashes, so the names go in a sidecar file
+        let names = write_names.then(|| common::encode_name_list(&files));
+        let times = args
+            .preserve_times
+            .then(|| {
+                common::encode_time_list(
+                    files
+                        .iter()
+                        .filter_map(|(source, _, name_hash)| Some((source.path()?, *name_hash))),
+                )
+            })
+            .transpose()?;
+
+        let mut entries: Vec<_> = files
+            .into_iter()
+            .map(|(source, rel_path, name_hash)| {
+                let compression = manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.compression(name_hash))
+                    .unwrap_or_else(|| args.compression.pick(&rel_path));
+                (source, rel_path, name_hash, compression)
+            })
+            .collect();
+
+        for (_, rel_path, name_hash, _) in &entries {
+            common::log_entry(&format!(
+                "Adding file: {} (hash: {})",
+                rel_path.display(),
+                name_hash
+            ));
+        }
+
+        let flags = ArchiveFlags(ArchiveFlagsValue::Protected.into());
+        let endian = Endian::Little; // TODO: let user pick endianness
+        let mut buf = Self::build(&entries, timestamp, flags, endian)?;
+
+        // The builder compresses entries while building, so check the sizes it wrote
+        let (archive, _) = common::read_bar(&buf)?;
+        let grown_sizes: HashMap<i32, (usize, usize)> = archive
+            .entries
+            .iter()
+            .filter(|entry| {
+                entry.location.1 != CompressionType::None
+                    && entry.compressed_size > entry.uncompressed_size
+            })
+            .map(|entry| {
+                (
+                    entry.name_hash.0,
+                    (
+                        entry.uncompressed_size as usize,
+                        entry.compressed_size as usize,
+                    ),
+                )
+            })
+            .collect();
+
+        let grown: Vec<_> = entries
+            .iter()
+            .filter_map(|(_, rel_path, name_hash, _)| {
+                grown_sizes
+                    .get(&name_hash.0)
+                    .map(|&(size, compressed_size)| (rel_path.as_path(), size, compressed_size))
+            })
+            .collect();
+        common::report_grown_entries(&grown, args.auto_raw);
+
+        let total_size = archive
+            .entries
+            .iter()
+            .map(|entry| u64::from(entry.uncompressed_size))
+            .sum();
+        summary::record_entries(archive.entries.len(), total_size);
+
+        if args.auto_raw && !grown_sizes.is_empty() {
+            for (_, _, name_hash, compression) in &mut entries {
+                if grown_sizes.contains_key(&name_hash.0) {
+                    *compression = CompressionType::None;
+                }
+            }
+            buf = Self::build(&entries, timestamp, flags, endian)?;
+        }
+
+        let output_file = common::create_output_file(output)?;
+        std::io::copy(&mut buf.as_slice(), &mut &output_file)
+            .map_err(|e| CliError::io("failed to write archive", e))?;
+
+        if let Some(names) = names {
+            let names_path = common::sidecar_names_path(output);
+            std::fs::write(&names_path, names).map_err(|e| {
+                CliError::io(
+                    format!("failed to write names file {}", names_path.display()),
+                    e,
+                )
+            })?;
+            common::log_info(&format!("Wrote file names to {}", names_path.display()));
+        }
+        if let Some(times) = times {
+            common::write_time_list(output, &times)?;
+        }
+
+        common::print_created(
+            &format!("Created BAR archive: {}", output.display()),
+            output,
+            start,
+        );
+        Ok(())
+    }
+
+    /// Builds a BAR archive from `(source, archive name, name hash, compression)` entries.
+    pub fn build(
+        entries: &[(InputSource, PathBuf, AfsHash, CompressionType)],
+        timestamp: Option<i32>,
+        flags: ArchiveFlags,
+        endian: Endian,
+    ) -> Result<Vec<u8>, CliError> {
         // let mut archive_writer = hdk_archive::bar::writer::BarWriter::default()
         //     .with_default_key(BAR_DEFAULT_KEY)
         //     .with_signature_key(BAR_SIGNATURE_KEY)
         //     .with_flags(ArchiveFlagsValue::Protected.into());
-        let mut archive_writer = BarBuilder::new(BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY)
-            .with_flags(ArchiveFlags(ArchiveFlagsValue::Protected.into()));
-
-        // Check if the input directory has a `.time` file for timestamp.
-        // If so, parse as i32 and use it as the archive timestamp.
-        let time_path = input.join(".time");
-        if time_path.exists() {
-            let time_bytes = common::read_file_bytes(&time_path)
-                .map_err(|e| format!("failed to read .time file: {e}"))?;
-
-            if time_bytes.len() == 4 {
-                // Always read as BE
-                let timestamp = i32::from_be_bytes([
-                    time_bytes[0],
-                    time_bytes[1],
-                    time_bytes[2],
-                    time_bytes[3],
-                ]);
-                archive_writer = archive_writer.with_timestamp(timestamp);
-                println!("Using timestamp from .time file: {}", timestamp);
-            } else {
-                println!(
-                    "Warning: .time file has invalid length, using default timestamp (system time)."
-                );
-            }
+        let mut archive_writer =
+            BarBuilder::new(BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY).with_flags(flags);
+        if let Some(timestamp) = timestamp {
+            archive_writer = archive_writer.with_timestamp(timestamp);
         }
 
-        let mut files = common::collect_input_files(input)?;
+        let progress = common::progress_bar(entries.len());
+        for (source, _, name_hash, compression) in entries {
+            let data = source.read()?;
 
-        // Sort ascending by signed AfsHash value
-        // This ensures they're written in the same order as the input files
-        files.sort_by_key(|(_, _, a_hash)| a_hash.0);
+            archive_writer.add_entry(*name_hash, data, *compression);
+            progress.inc(1);
+        }
+        progress.finish_and_clear();
+
+        let mut buf = Vec::new();
+        let mut writer = std::io::Cursor::new(&mut buf);
+
+        archive_writer
+            .build(&mut writer, endian)
+            .map_err(|e| format!("failed to finalize archive: {e}"))?;
+
+        Ok(buf)
+    }
+
+    pub fn extract(args: &ExtractArgs) -> Result<(), CliError> {
+        let (input, output) = (args.io.input.as_path(), args.io.output.as_path());
+        summary::record_input(input);
+
+        let data = common::read_archive(input)?;
+
+        let (archive, _) = common::read_bar(&data)?;
+        let entries = common::extract_selection(args, &archive.entries)?;
+
+        if args.dry_run {
+            let namer = common::OutputNamer::new(args, ".bin", None)?;
+            common::preview_extract(args, &namer, &entries);
+            return Ok(());
+        }
+        common::create_output_dir(output, args.merge)?;
+
+        let time = archive.archive_data.timestamp;
+        let times = common::EntryTimes::load(args, time)?;
+        let overwrite = args.overwrite_policy();
+
+        let namer = common::OutputNamer::new(args, ".bin", None)?;
+        let progress = common::progress_bar(entries.len());
+
+        // Returns the hash of an entry that failed to decode, unless `--fail-fast` is given
+        let extract_entry = |index: usize, entry: &Entry| {
+            // Each thread gets its own view of the data
+            let mut reader = std::io::Cursor::new(&data);
+            let file_data = common::timed(
+                || format!("Decoding {}", entry.name_hash),
+                || archive.entry_data(&mut reader, entry, &BAR_DEFAULT_KEY, &BAR_SIGNATURE_KEY),
+            )
+            .map_err(|e| {
+                CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+            });
+            let Some(file_data) = common::salvage_entry(args, file_data)? else {
+                progress.inc(1);
+                return Ok(Some(entry.name_hash));
+            };
+            common::check_entry(args, entry, &file_data);
+
+            let output_path = namer.path(index, entry.name_hash);
+            common::write_extracted_file(
+                &output_path,
+                &file_data,
+                times.get(entry.name_hash),
+                overwrite,
+            )?;
+            common::extract_nested(args, &output_path, &file_data)?;
+
+            progress.inc(1);
+            Ok::<_, CliError>(None)
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let failed: Vec<AfsHash> = common::with_threads(args.jobs, "--jobs", || {
+            entries
+                .iter()
+                .filter_map(|&(index, entry)| extract_entry(index, entry).transpose())
+                .collect::<Result<_, _>>()
+        })??;
+
+        #[cfg(feature = "rayon")]
+        let failed: Vec<AfsHash> = common::with_threads(args.jobs, "--jobs", || {
+            common::report_parallelism();
+
+            entries
+                .par_iter()
+                .filter_map(|&(index, entry)| extract_entry(index, entry).transpose())
+                .collect::<Result<_, _>>()
+        })??;
+        progress.finish_and_clear();
+        namer.report();
+
+        // Save the `.time` with the archive's endianess in the output folder root
+        let time_path = output.join(".time");
+
+        // Always write the timestamp in big-endian for consistency
+        std::fs::write(&time_path, time.to_be_bytes())
+            .map_err(|e| CliError::io("failed to write .time file", e))?;
+        common::check_failed_entries(entries.len(), failed)?;
+
+        common::print_result(
+            &format!("Extracted {} files to {}", entries.len(), output.display()),
+            &serde_json::json!({ "output": output, "entries": entries.len() }),
+        );
+        Ok(())
+    }
+
+    pub fn inspect(input: &Path) -> Result<(), CliError> {
+        summary::record_input(input);
+
+        let data = common::read_archive(input)?;
+        let (archive, _) = common::read_bar(&data)?;
+        summary::record_entries(archive.entries.len(), 0);
+
+        common::print_archive_header(&data, archive.archive_data.timestamp, &archive.entries);
+        Ok(())
+    }
+
+    pub fn remove(args: &RemoveArgs) -> Result<(), CliError> {
+        summary::record_input(&args.io.input);
+
+        let data = common::read_archive(&args.io.input)?;
+
+        let (archive, endian) = common::read_bar(&data)?;
+
+        let present: Vec<_> = archive
+            .entries
+            .iter()
+            .map(|entry| entry.name_hash)
+            .collect();
+        let selected = common::select_entries(
+            &present,
+            &args.hashes,
+            &args.patterns,
+            args.names.as_deref(),
+        )?;
+
+        if selected.is_empty() {
+            return Err(CliError::InvalidArgument(
+                "no entries matched; archive left unchanged".to_string(),
+            ));
+        }
+
+        // Keep the source archive's flags and timestamp
+        let mut archive_writer = BarBuilder::new(BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY)
+            .with_flags(ArchiveFlags(archive.archive_data.flags.0))
+            .with_timestamp(archive.archive_data.timestamp);
+
+        for entry in &archive.entries {
+            if selected.contains(&entry.name_hash.0) {
+                common::log_info(&format!("Removing entry: {}", entry.name_hash));
+                continue;
+            }
 
-        for (abs_path, rel_path, name_hash) in files {
-            let data = common::read_file_bytes(&abs_path)
-                .map_err(|e| format!("failed to read file {}: {e}", abs_path.display()))?;
+            let mut reader = std::io::Cursor::new(&data);
+            let file_data = archive
+                .entry_data(&mut reader, entry, &BAR_DEFAULT_KEY, &BAR_SIGNATURE_KEY)
+                .map_err(|e| {
+                    CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+                })?;
 
-            println!("Adding file: {} (hash: {})", rel_path.display(), name_hash);
+            summary::record_entries(1, file_data.len() as u64);
 
+            // Keep each remaining entry's original compression type
             archive_writer.add_entry(
-                name_hash,
-                data,
-                hdk_archive::structs::CompressionType::Encrypted,
+                entry.name_hash,
+                SmallVec::from_vec(file_data),
+                entry.location.1,
             );
         }
 
         let mut buf = Vec::new();
-        let endian = Endian::Little; // TODO: let user pick endianness
         let mut writer = std::io::Cursor::new(&mut buf);
 
         archive_writer
             .build(&mut writer, endian)
             .map_err(|e| format!("failed to finalize archive: {e}"))?;
 
-        let output_file = common::create_output_file(output)?;
+        let output_file = common::create_output_file(&args.io.output)?;
         std::io::copy(&mut buf.as_slice(), &mut &output_file)
-            .map_err(|e| format!("failed to write archive: {e}"))?;
+            .map_err(|e| CliError::io("failed to write archive", e))?;
 
-        println!("Created BAR archive: {}", output.display());
+        common::print_result(
+            &format!(
+                "Removed {} of {} entries, wrote {}",
+                selected.len(),
+                archive.entries.len(),
+                args.io.output.display()
+            ),
+            &serde_json::json!({ "output": args.io.output, "removed": selected.len() }),
+        );
         Ok(())
     }
 
-    pub fn extract(input: &Path, output: &Path) -> Result<(), String> {
-        let data = common::read_file_bytes(input)
-            .map_err(|e| format!("failed to read archive file {}: {e}", input.display()))?;
+    pub fn append(args: &AppendArgs) -> Result<(), CliError> {
+        summary::record_input(&args.io.input);
 
-        let magic: [u8; 4] = data
-            .get(0..4)
-            .ok_or_else(|| "File too small to be a valid archive".to_string())?
-            .try_into()
-            .unwrap();
-        let endian: Endian = magic::magic_to_endianess(&magic).into();
+        let data = common::read_archive(&args.io.input)?;
+        let (archive, endian) = common::read_bar(&data)?;
+        let entries = common::plan_append(args, &archive.entries)?;
 
-        common::create_output_dir(output)?;
-        let mut reader = std::io::Cursor::new(&data);
+        // Keep the source archive's flags and timestamp
+        let mut archive_writer = BarBuilder::new(BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY)
+            .with_flags(ArchiveFlags(archive.archive_data.flags.0))
+            .with_timestamp(archive.archive_data.timestamp);
 
-        let archive = match endian {
-            Endian::Little => BarArchive::read_le_args(
-                &mut reader,
-                (BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY, data.len() as u32),
-            ),
-            Endian::Big => BarArchive::read_be_args(
-                &mut reader,
-                (BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY, data.len() as u32),
-            ),
-        }
-        .map_err(|e| format!("failed to open BAR archive: {e}"))?;
+        for entry in &entries {
+            let (file_data, compression) = match entry {
+                AppendEntry::Existing(entry) => {
+                    let mut reader = std::io::Cursor::new(&data);
+                    let file_data = archive
+                        .entry_data(&mut reader, entry, &BAR_DEFAULT_KEY, &BAR_SIGNATURE_KEY)
+                        .map_err(|e| {
+                            CliError::Decode(format!(
+                                "failed to read entry {}: {e}",
+                                entry.name_hash
+                            ))
+                        })?;
 
-        for entry in &archive.entries {
-            let file_data = archive
-                .entry_data(&mut reader, entry, &BAR_DEFAULT_KEY, &BAR_SIGNATURE_KEY)
-                .map_err(|e| format!("failed to read entry data: {e}"))?;
-
-            let output_path = output.join(format!("{}.bin", entry.name_hash));
+                    // Keep each existing entry's original compression type
+                    (SmallVec::from_vec(file_data), entry.location.1)
+                }
+                AppendEntry::New { path, rel_path, .. } => {
+                    let file_data = common::read_file_bytes(path).map_err(|e| {
+                        CliError::io(format!("failed to read file {}", path.display()), e)
+                    })?;
+                    common::log_entry(&format!("Adding file: {}", rel_path.display()));
+                    (file_data, args.compression.pick(rel_path))
+                }
+            };
 
-            std::fs::write(&output_path, file_data)
-                .map_err(|e| format!("failed to write file {}: {e}", output_path.display()))?;
+            summary::record_entries(1, file_data.len() as u64);
+            archive_writer.add_entry(entry.name_hash(), file_data, compression);
         }
 
-        // Save the `.time` with the archive's endianess in the output folder root
-        let time = archive.archive_data.timestamp;
-        let time_path = output.join(".time");
+        let mut buf = Vec::new();
+        let mut writer = std::io::Cursor::new(&mut buf);
 
-        // Always write the timestamp in big-endian for consistency
-        std::fs::write(&time_path, time.to_be_bytes())
-            .map_err(|e| format!("failed to write .time file: {e}"))?;
+        archive_writer
+            .build(&mut writer, endian)
+            .map_err(|e| format!("failed to finalize archive: {e}"))?;
 
-        println!(
-            "Extracted {} files to {}",
-            archive.entries.len(),
-            output.display()
+        let output_file = common::create_output_file(&args.io.output)?;
+        std::io::copy(&mut buf.as_slice(), &mut &output_file)
+            .map_err(|e| CliError::io("failed to write archive", e))?;
+
+        common::print_result(
+            &format!(
+                "Wrote {} entries to {}",
+                entries.len(),
+                args.io.output.display()
+            ),
+            &serde_json::json!({ "output": args.io.output, "entries": entries.len() }),
         );
         Ok(())
     }
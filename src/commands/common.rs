@@ -1,139 +1,2027 @@
 //! Common utilities for archive commands.
 
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use binrw::{BinRead, Endian};
+use filetime::FileTime;
+use hdk_archive::{
+    bar::structs::BarArchive,
+    sharc::{builder::SharcBuilder, structs::SharcArchive},
+    structs::{ArchiveVersion, CompressionType},
+};
 use hdk_secure::hash::AfsHash;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::RngExt;
 use smallvec::SmallVec;
 
+use crate::{
+    commands::{
+        AppendArgs, CompressedFile, CreateArgs, ExtractArgs, NamingStrategy, OverwritePolicy,
+        sdat::SDAT_KEYS, summary,
+    },
+    error::CliError,
+    keys::{BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY, SHARC_DEFAULT_KEY, SHARC_SDAT_KEY},
+    magic,
+};
+
+/// Returns the directory intermediate files should go in.
+///
+/// This is `--tmp-dir` if given, otherwise the system temp directory.
+pub fn temp_dir() -> PathBuf {
+    crate::commands::globals()
+        .tmp_dir
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Creates a new, uniquely named folder in [`temp_dir`].
+///
+/// The caller is responsible for removing the folder once it's done with it.
+pub fn create_temp_dir(prefix: &str) -> Result<PathBuf, CliError> {
+    let dir = temp_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| {
+        CliError::io(
+            format!("failed to create temp directory {}", dir.display()),
+            e,
+        )
+    })?;
+
+    loop {
+        let path = dir.join(format!("hdk-{prefix}-{:016x}", rand::random::<u64>()));
+        match std::fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(CliError::io(
+                    format!("failed to create temp directory {}", path.display()),
+                    e,
+                ));
+            }
+        }
+    }
+}
+
+/// Set once the user has agreed to overwrite every existing output of a batch, so
+/// the per-output prompts are skipped.
+static OVERWRITE_ALL: AtomicBool = AtomicBool::new(false);
+
+/// Asks the user to confirm overwriting `what`.
+///
+/// `--yes`, or agreeing to overwrite a whole batch, confirms without asking. Without
+/// a terminal to ask on, this refuses instead of waiting for an answer that can't come.
+fn confirm_overwrite(prompt: String, what: &str) -> Result<bool, CliError> {
+    if crate::commands::globals().yes || OVERWRITE_ALL.load(Ordering::Relaxed) {
+        return Ok(true);
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Err(CliError::UserDeclined(format!(
+            "refusing to overwrite {what} in non-interactive mode; pass --yes"
+        )));
+    }
+
+    Ok(dialoguer::Confirm::new().with_prompt(prompt).interact()?)
+}
+
+/// Asks once whether to overwrite whichever of a batch's `outputs` already exist,
+/// instead of prompting for each of them.
+pub fn confirm_overwrite_all<'a>(
+    outputs: impl IntoIterator<Item = &'a Path>,
+) -> Result<(), CliError> {
+    let existing: Vec<_> = outputs.into_iter().filter(|path| path.exists()).collect();
+    if existing.is_empty() {
+        return Ok(());
+    }
+
+    for path in &existing {
+        log_info(&format!("Already exists: {}", path.display()));
+    }
+    if !confirm_overwrite(
+        format!("{} outputs already exist. Overwrite all?", existing.len()),
+        &format!("{} existing outputs", existing.len()),
+    )? {
+        return Err(CliError::UserDeclined(format!(
+            "{} outputs already exist and were not overwritten.",
+            existing.len()
+        )));
+    }
+
+    OVERWRITE_ALL.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
 /// Confirm overwriting an existing file.
 /// Returns `Ok(File)` if the user confirms or file doesn't exist.
 /// Returns `Err` if the user declines or an I/O error occurs.
-pub fn create_output_file(path: &Path) -> Result<std::fs::File, String> {
+pub fn create_output_file(path: &Path) -> Result<std::fs::File, CliError> {
+    summary::record_output(path);
+
     match std::fs::File::create_new(path) {
         Ok(f) => Ok(f),
         Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-            if dialoguer::Confirm::new()
-                .with_prompt(format!(
-                    "File `{}` already exists. Overwrite?",
-                    path.display()
-                ))
-                .interact()
-                .map_err(|e| format!("failed to read user input: {e}"))?
-            {
-                std::fs::File::create(path)
-                    .map_err(|e| format!("failed to create file {}: {e}", path.display()))
+            if confirm_overwrite(
+                format!("File `{}` already exists. Overwrite?", path.display()),
+                &format!("`{}`", path.display()),
+            )? {
+                std::fs::File::create(path).map_err(|e| {
+                    CliError::io(format!("failed to create file {}", path.display()), e)
+                })
             } else {
-                Err(format!(
+                Err(CliError::UserDeclined(format!(
                     "File `{}` already exists and was not overwritten.",
                     path.display()
-                ))
+                )))
             }
         }
-        Err(e) => Err(format!("failed to create file {}: {e}", path.display())),
+        Err(e) => Err(CliError::io(
+            format!("failed to create file {}", path.display()),
+            e,
+        )),
     }
 }
 
+/// Set once a command starts writing its output to stdout, so messages go to stderr instead.
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Whether `path` is `-`, meaning stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Opens `path` for reading, or stdin if it's `-`.
+pub fn open_input(path: &Path) -> Result<Box<dyn Read>, CliError> {
+    if is_stdio(path) {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+
+    File::open(path)
+        .map(|file| Box::new(file) as Box<dyn Read>)
+        .map_err(|e| CliError::io(format!("failed to open input file {}", path.display()), e))
+}
+
+/// Reads all of `path`, or stdin if it's `-`.
+pub fn read_input(path: &Path) -> Result<Vec<u8>, CliError> {
+    let mut data = Vec::new();
+    open_input(path)?
+        .read_to_end(&mut data)
+        .map_err(|e| CliError::io(format!("failed to read {}", path.display()), e))?;
+    Ok(data)
+}
+
+/// Opens `path` for writing like [`create_output_file`], or stdout if it's `-`.
+///
+/// Writing to stdout needs no confirmation, and moves all other output to stderr.
+pub fn open_output(path: &Path) -> Result<Box<dyn Write>, CliError> {
+    if is_stdio(path) {
+        STDOUT_TAKEN.store(true, Ordering::Relaxed);
+        return Ok(Box::new(std::io::stdout().lock()));
+    }
+
+    create_output_file(path).map(|file| Box::new(file) as Box<dyn Write>)
+}
+
 /// Create an output directory, prompting to proceed if it already exists.
-pub fn create_output_dir(path: &Path) -> Result<(), String> {
-    if path.exists() {
-        if !dialoguer::Confirm::new()
-            .with_prompt(format!(
+///
+/// With `merge` set, an existing directory is used as-is and existing files are
+/// handled individually by [`write_extracted_file`].
+pub fn create_output_dir(path: &Path, merge: bool) -> Result<(), CliError> {
+    summary::record_output(path);
+
+    if path.exists() && !merge {
+        if !confirm_overwrite(
+            format!(
                 "Output folder `{}` already exists. Proceed?",
                 path.display()
-            ))
-            .interact()
-            .map_err(|e| format!("failed to read user input: {e}"))?
-        {
-            return Err(format!(
+            ),
+            &format!("`{}`", path.display()),
+        )? {
+            return Err(CliError::UserDeclined(format!(
                 "Output folder `{}` already exists and was not overwritten.",
                 path.display()
-            ));
+            )));
         }
     } else {
         std::fs::create_dir_all(path)
-            .map_err(|e| format!("failed to create output folder: {e}"))?;
+            .map_err(|e| CliError::io("failed to create output folder", e))?;
     }
     Ok(())
 }
 
-/// Collects all files in a directory (recursively) or returns a single file.
+/// With `--dry-run`, reports whether `path` would be created or overwritten.
+pub fn preview_output(path: &Path) {
+    if path.exists() {
+        println!("Would overwrite {}", path.display());
+    } else {
+        println!("Would write {}", path.display());
+    }
+}
+
+/// With `--dry-run`, lists the files a create command would add to `output`.
+pub fn preview_create<'a>(files: impl IntoIterator<Item = (&'a Path, AfsHash)>, output: &Path) {
+    let mut count = 0;
+    for (rel_path, name_hash) in files {
+        println!("Would add {} (hash: {name_hash})", rel_path.display());
+        count += 1;
+    }
+
+    preview_output(output);
+    println!("Dry run: {count} files, nothing was written");
+}
+
+/// With `--dry-run`, lists where each of `entries` would be extracted to.
+pub fn preview_extract(
+    args: &ExtractArgs,
+    namer: &OutputNamer,
+    entries: &[(usize, &hdk_archive::structs::Entry)],
+) {
+    let output = args.io.output.as_path();
+    if output.exists() && !args.merge {
+        println!("Would overwrite folder {}", output.display());
+    }
+
+    let mut count = 0;
+    for &(index, entry) in entries {
+        if namer.skips(entry.name_hash) {
+            continue;
+        }
+
+        let path = namer.path(index, entry.name_hash);
+        if path.exists() {
+            println!(
+                "Would overwrite {} with {}",
+                path.display(),
+                entry.name_hash
+            );
+        } else {
+            println!("Would extract {} to {}", entry.name_hash, path.display());
+        }
+        count += 1;
+    }
+
+    println!("Dry run: {count} entries, nothing was written");
+}
+
+/// Feeds raw bytes into an `AfsHash`, for data that isn't a string; start from `AfsHash(0)`.
 ///
-/// Calculates and returns the `AfsHash` for each file so callers get a well-formed
-/// (absolute path, relative path, name-hash) tuple.
-pub fn collect_input_files(input: &Path) -> Result<Vec<(PathBuf, PathBuf, AfsHash)>, String> {
-    if input.is_file() {
-        let file_name = input
-            .file_name()
-            .map(PathBuf::from)
-            .unwrap_or_else(|| PathBuf::from("file"));
+/// `AfsHash` multiplies the running value by 37 and adds the next byte, wrapping at
+/// 32 bits. `hdk-secure` only exposes it over `&str`, so this is the same fold over a
+/// byte slice, which can be fed in chunks. For ASCII text it gives the same hash as
+/// `AfsHash::new_from_str`.
+pub fn afs_hash_update(hash: AfsHash, data: &[u8]) -> AfsHash {
+    AfsHash(data.iter().fold(hash.0, |hash, &byte| {
+        hash.wrapping_mul(37).wrapping_add(i32::from(byte))
+    }))
+}
+
+/// Computes the `AfsHash` of an archive path, normalized to lowercase with forward slashes.
+pub fn hash_path(path: &str) -> AfsHash {
+    AfsHash::new_from_str(&path.to_lowercase().replace('\\', "/"))
+}
+
+/// Parses an 8-digit hex string (as used for extracted file names) into an `AfsHash`.
+///
+/// An optional `0x` prefix is accepted. This is also the clap value parser for every
+/// `--hash` argument, so all commands parse hashes the same way.
+pub fn parse_hash(hex_str: &str) -> Result<AfsHash, CliError> {
+    let digits = hex_str
+        .strip_prefix("0x")
+        .or_else(|| hex_str.strip_prefix("0X"))
+        .unwrap_or(hex_str);
+
+    if digits.len() != 8 {
+        return Err(CliError::InvalidArgument(format!(
+            "invalid hash '{hex_str}': expected 8 hex digits, got {}",
+            digits.len()
+        )));
+    }
+
+    let hash_val = hex::decode(digits)
+        .map_err(|e| CliError::InvalidArgument(format!("invalid hex in hash '{hex_str}': {e}")))?;
+    let bytes: [u8; 4] = hash_val.as_slice().try_into().map_err(|_| {
+        CliError::InvalidArgument(format!("invalid hash bytes length for '{hex_str}'"))
+    })?;
+
+    Ok(AfsHash(i32::from_be_bytes(bytes)))
+}
+
+/// Whether a file name is a raw name hash (8 hex digits) rather than a real path.
+fn is_hash_name(name: &str) -> bool {
+    name.len() == 8 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Derives the name hash of an input file from its relative path.
+///
+/// Files that are named after a raw hash (8 hex digits, as produced by extraction)
+/// keep that hash, including when sharded into a folder named after its prefix
+/// (`AB/ABCDEF12`, as produced by `--shard`). Anything else is hashed from its path.
+pub fn input_name_hash(rel_path: &str) -> Result<AfsHash, CliError> {
+    let normalized = rel_path.replace('\\', "/");
+    let sharded = normalized
+        .split_once('/')
+        .filter(|(prefix, name)| is_hash_name(name) && name.starts_with(prefix));
+
+    if let Some((_, name)) = sharded {
+        parse_hash(name)
+    } else if is_hash_name(rel_path) {
+        parse_hash(rel_path)
+    } else {
+        Ok(hash_path(rel_path))
+    }
+}
+
+/// Builds the output path of a hash-named entry.
+///
+/// With `shard` set, the file is nested under a folder named after the first `shard`
+/// characters of its name (e.g. `AB/ABCDEF12`), keeping directories small.
+pub fn hash_output_path(output: &Path, file_name: &str, shard: Option<u8>) -> PathBuf {
+    let dir = shard
+        .and_then(|n| file_name.get(..n.into()))
+        .map_or_else(|| output.to_path_buf(), |prefix| output.join(prefix));
+
+    dir.join(file_name)
+}
+
+/// Number of hex digits `--naming sharded` nests by when `--shard` isn't given.
+const DEFAULT_SHARD: u8 = 2;
+
+/// Works out the output path of each extracted entry, according to `--naming`.
+pub struct OutputNamer<'a> {
+    output: &'a Path,
+    strategy: NamingStrategy,
+    shard: Option<u8>,
+    extension: &'static str,
+    names: Option<HashMap<i32, PathBuf>>,
+    uses_embedded: bool,
+    /// How many entries were named, and how many of those by a known name
+    named: AtomicUsize,
+    resolved: AtomicUsize,
+}
+
+impl<'a> OutputNamer<'a> {
+    /// Sets up naming for an extraction.
+    ///
+    /// `extension` is appended to hash and index names, and `embedded` holds the
+    /// archive's embedded names, if it has any.
+    pub fn new(
+        args: &'a ExtractArgs,
+        extension: &'static str,
+        embedded: Option<HashMap<i32, PathBuf>>,
+    ) -> Result<Self, CliError> {
+        let uses_embedded = embedded.is_some()
+            && matches!(args.naming, NamingStrategy::Auto | NamingStrategy::Embedded);
+
+        let names = match args.naming {
+            // Fill in whatever the embedded names don't cover from `--names`
+            NamingStrategy::Auto => match &args.names {
+                Some(path) => {
+                    let mut names = resolve_names(&load_name_list(path)?);
+                    names.extend(embedded.unwrap_or_default());
+                    Some(names)
+                }
+                None => embedded,
+            },
+            NamingStrategy::Embedded => {
+                if embedded.is_none() {
+                    log_info("Warning: the archive has no embedded names; naming files by hash.");
+                }
+                embedded
+            }
+            NamingStrategy::ResolvedName => {
+                let path = args
+                    .names
+                    .as_deref()
+                    .ok_or("`--naming resolved-name` requires `--names`")?;
+                Some(resolve_names(&load_name_list(path)?))
+            }
+            NamingStrategy::FullHash | NamingStrategy::Sharded | NamingStrategy::Index => None,
+        };
+
+        if let Some(names) = &names {
+            log_info(&format!("Restoring {} file names", names.len()));
+        }
+
+        Ok(Self {
+            output: args.io.output.as_path(),
+            strategy: args.naming,
+            shard: args.shard,
+            extension,
+            names,
+            uses_embedded,
+            named: AtomicUsize::new(0),
+            resolved: AtomicUsize::new(0),
+        })
+    }
+
+    /// Prints how many of the extracted entries got a known name, if any names were loaded.
+    pub fn report(&self) {
+        if self.names.is_some() {
+            log_info(&format!(
+                "Resolved {} of {} entries to their real names",
+                self.resolved.load(Ordering::Relaxed),
+                self.named.load(Ordering::Relaxed)
+            ));
+        }
+    }
+
+    /// Whether `name_hash` is the embedded names entry, which isn't written out
+    /// when its names are used.
+    pub fn skips(&self, name_hash: AfsHash) -> bool {
+        self.uses_embedded && name_hash.0 == hash_path(EMBEDDED_NAMES_PATH).0
+    }
+
+    /// The output path of the entry at `index` in the archive's entry table.
+    pub fn path(&self, index: usize, name_hash: AfsHash) -> PathBuf {
+        let hash_name = format!("{name_hash}{}", self.extension);
+
+        match self.strategy {
+            NamingStrategy::FullHash => self.output.join(hash_name),
+            NamingStrategy::Sharded => hash_output_path(
+                self.output,
+                &hash_name,
+                Some(self.shard.unwrap_or(DEFAULT_SHARD)),
+            ),
+            NamingStrategy::Index => self.output.join(format!("{index}{}", self.extension)),
+            NamingStrategy::Auto | NamingStrategy::ResolvedName | NamingStrategy::Embedded => {
+                self.named.fetch_add(1, Ordering::Relaxed);
+
+                self.names
+                    .as_ref()
+                    .and_then(|names| names.get(&name_hash.0))
+                    .map_or_else(
+                        || hash_output_path(self.output, &hash_name, self.shard),
+                        |path| {
+                            self.resolved.fetch_add(1, Ordering::Relaxed);
+                            self.output.join(path)
+                        },
+                    )
+            }
+        }
+    }
+}
+
+/// Decides whether an existing output file should be overwritten with `data`.
+fn should_overwrite(
+    path: &Path,
+    data: &[u8],
+    mtime: Option<i32>,
+    overwrite: OverwritePolicy,
+) -> Result<bool, CliError> {
+    // Serialize prompts so parallel writers don't interleave them
+    static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
+    match overwrite {
+        OverwritePolicy::Always => Ok(true),
+        OverwritePolicy::Never => Ok(false),
+        OverwritePolicy::NewerOnly => is_newer_entry(path, data, mtime),
+        OverwritePolicy::Prompt => {
+            let _guard = PROMPT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            confirm_overwrite(
+                format!("File `{}` already exists. Overwrite?", path.display()),
+                &format!("`{}`", path.display()),
+            )
+        }
+    }
+}
+
+/// Checks whether an entry should replace the existing file at `path`.
+///
+/// If the entry has a timestamp, files modified at or after it are kept. Otherwise,
+/// or if the entry is newer, the file is only replaced if its content differs.
+fn is_newer_entry(path: &Path, data: &[u8], mtime: Option<i32>) -> Result<bool, CliError> {
+    let read_err = |e: std::io::Error| format!("failed to read {}: {e}", path.display());
+
+    if let Some(mtime) = mtime {
+        let metadata = std::fs::metadata(path).map_err(read_err)?;
+        let modified = FileTime::from_last_modification_time(&metadata).unix_seconds();
+        if modified >= i64::from(mtime) {
+            return Ok(false);
+        }
+    }
+
+    let existing = std::fs::read(path).map_err(read_err)?;
+    Ok(existing != data)
+}
+
+/// Writes an extracted entry to disk.
+///
+/// Existing files are handled according to `overwrite`; skipped files are left untouched.
+/// Missing parent directories are created.
+/// If `mtime` (a Unix timestamp) is given, it's applied as the file's modification time.
+pub fn write_extracted_file(
+    path: &Path,
+    data: &[u8],
+    mtime: Option<i32>,
+    overwrite: OverwritePolicy,
+) -> Result<(), CliError> {
+    if path.exists() && !should_overwrite(path, data, mtime, overwrite)? {
+        log_info(&format!("Skipping existing file: {}", path.display()));
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            CliError::io(
+                format!("failed to create directory {}", parent.display()),
+                e,
+            )
+        })?;
+    }
+
+    std::fs::write(path, data)
+        .map_err(|e| CliError::io(format!("failed to write output file {}", path.display()), e))?;
+
+    if let Some(mtime) = mtime {
+        filetime::set_file_mtime(path, FileTime::from_unix_time(mtime.into(), 0)).map_err(|e| {
+            CliError::io(
+                format!("failed to set modification time of {}", path.display()),
+                e,
+            )
+        })?;
+    }
+
+    summary::record_entries(1, data.len() as u64);
+    Ok(())
+}
+
+/// Path of the modification times file written next to `archive` by `create --preserve-times`.
+pub fn sidecar_times_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".times");
+    PathBuf::from(path)
+}
+
+/// Builds a modification times file from `(source file, name hash)` pairs, with one
+/// `HASH<TAB>seconds` line per file. Times that don't fit in 32 bits are left out.
+pub fn encode_time_list<'a>(
+    files: impl IntoIterator<Item = (&'a Path, AfsHash)>,
+) -> Result<Vec<u8>, CliError> {
+    let mut contents = String::from("# name hash, modification time (Unix seconds)\n");
+    for (path, name_hash) in files {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| CliError::io(format!("failed to read {}", path.display()), e))?;
+        let modified = FileTime::from_last_modification_time(&metadata).unix_seconds();
+
+        if let Ok(modified) = i32::try_from(modified) {
+            contents += &format!("{name_hash}\t{modified}\n");
+        }
+    }
+
+    Ok(contents.into_bytes())
+}
+
+/// Writes the modification times file for `archive`, as built by [`encode_time_list`].
+pub fn write_time_list(archive: &Path, times: &[u8]) -> Result<(), CliError> {
+    let path = sidecar_times_path(archive);
+    std::fs::write(&path, times).map_err(|e| {
+        CliError::io(
+            format!("failed to write modification times file {}", path.display()),
+            e,
+        )
+    })?;
+
+    log_info(&format!("Wrote modification times to {}", path.display()));
+    Ok(())
+}
 
-        let raw_path_str = file_name.to_string_lossy().to_string();
-        let name_hash =
-            if raw_path_str.len() == 8 && raw_path_str.chars().all(|c| c.is_ascii_hexdigit()) {
-                let hash_val = hex::decode(&raw_path_str)
-                    .map_err(|e| format!("invalid hex in filename '{}': {e}", raw_path_str))?;
-                let bytes: [u8; 4] = hash_val
-                    .as_slice()
-                    .try_into()
-                    .map_err(|_| format!("invalid hash bytes length for '{}'", raw_path_str))?;
-                AfsHash(i32::from_be_bytes(bytes))
+/// The modification times to give extracted files.
+pub struct EntryTimes {
+    /// The archive's timestamp, unless `--no-preserve-times` was given
+    archive: Option<i32>,
+    /// Each entry's own time, from the archive's `.times` file with `--preserve-times`
+    entries: HashMap<i32, i32>,
+}
+
+impl EntryTimes {
+    /// Sets up modification times for an extraction from an archive with the timestamp
+    /// `archive_time`.
+    pub fn load(args: &ExtractArgs, archive_time: i32) -> Result<Self, CliError> {
+        let archive = (!args.no_preserve_times).then_some(archive_time);
+        let mut entries = HashMap::new();
+
+        let path = sidecar_times_path(&args.io.input);
+        if args.preserve_times {
+            if is_stdio(&args.io.input) || !path.is_file() {
+                log_info(&format!(
+                    "Warning: {} not found; using the archive's timestamp for every file.",
+                    path.display()
+                ));
             } else {
-                let clean_path = raw_path_str.to_lowercase().replace("\\", "/");
-                AfsHash::new_from_str(&clean_path)
+                entries = load_time_list(&path)?;
+                log_info(&format!(
+                    "Restoring {} modification times from {}",
+                    entries.len(),
+                    path.display()
+                ));
+            }
+        }
+
+        Ok(Self { archive, entries })
+    }
+
+    /// The modification time for the entry `name_hash`, if it should get one.
+    pub fn get(&self, name_hash: AfsHash) -> Option<i32> {
+        self.entries.get(&name_hash.0).copied().or(self.archive)
+    }
+}
+
+/// Parses a modification times file into a map from name hash to Unix seconds.
+fn load_time_list(path: &Path) -> Result<HashMap<i32, i32>, CliError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        CliError::io(
+            format!("failed to read modification times file {}", path.display()),
+            e,
+        )
+    })?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            let malformed = || {
+                CliError::Decode(format!(
+                    "malformed line {} in {}: '{line}'",
+                    index + 1,
+                    path.display()
+                ))
             };
 
-        return Ok(vec![(input.to_path_buf(), file_name, name_hash)]);
+            let (hash, time) = line.split_once('\t').ok_or_else(malformed)?;
+            let hash = parse_hash(hash.trim()).map_err(|_| malformed())?;
+            let time = time.trim().parse().map_err(|_| malformed())?;
+            Ok((hash.0, time))
+        })
+        .collect()
+}
+
+/// Reads the archive timestamp from the `.time` file in `dir`, if there is one.
+///
+/// The timestamp is always stored big-endian. Returns `None` if the file is missing
+/// or malformed, in which case the default timestamp (system time) should be used.
+pub fn read_time_file(dir: &Path) -> Result<Option<i32>, CliError> {
+    let time_path = dir.join(".time");
+    if !time_path.exists() {
+        return Ok(None);
     }
 
-    if !input.is_dir() {
-        return Err(format!("Input path does not exist: {}", input.display()));
+    let time_bytes =
+        read_file_bytes(&time_path).map_err(|e| CliError::io("failed to read .time file", e))?;
+
+    let Ok(bytes) = <[u8; 4]>::try_from(time_bytes.as_slice()) else {
+        log_info("Warning: .time file has invalid length, using default timestamp (system time).");
+        return Ok(None);
+    };
+
+    // Always read as BE
+    let timestamp = i32::from_be_bytes(bytes);
+    log_info(&format!("Using timestamp from .time file: {}", timestamp));
+    Ok(Some(timestamp))
+}
+
+/// Timestamp of a `--reproducible` archive that has no `.time` file or manifest
+/// timestamp: `SOURCE_DATE_EPOCH` if it's set, otherwise 0.
+pub fn reproducible_timestamp() -> Result<i32, CliError> {
+    let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") else {
+        return Ok(0);
+    };
+
+    epoch
+        .trim()
+        .parse()
+        .map_err(|e| CliError::InvalidArgument(format!("invalid SOURCE_DATE_EPOCH '{epoch}': {e}")))
+}
+
+/// Where the data for a new archive entry comes from.
+#[derive(Debug, Clone)]
+pub enum InputSource {
+    /// A file on disk
+    File(PathBuf),
+    /// An entry read out of a `.zip` / `.tar` input
+    #[cfg(any(feature = "zip", feature = "tar"))]
+    Memory(Vec<u8>),
+}
+
+impl InputSource {
+    /// The file the entry is read from, if it's on disk.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            #[cfg(any(feature = "zip", feature = "tar"))]
+            Self::Memory(_) => None,
+        }
+    }
+
+    /// Reads the entry's data.
+    pub fn read(&self) -> Result<SmallVec<[u8; 16_384]>, CliError> {
+        match self {
+            Self::File(path) => read_file_bytes(path)
+                .map_err(|e| CliError::io(format!("failed to read file {}", path.display()), e)),
+            #[cfg(any(feature = "zip", feature = "tar"))]
+            Self::Memory(data) => Ok(SmallVec::from_slice(data)),
+        }
+    }
+}
+
+/// Gathers the files to add to a new archive.
+///
+/// Reads them from the `--from-list` file if given, otherwise from `--input`, which
+/// is walked as a folder or, with the `zip` / `tar` features, read as an archive.
+pub fn collect_create_inputs(
+    args: &CreateArgs,
+) -> Result<Vec<(InputSource, PathBuf, AfsHash)>, CliError> {
+    if let Some(path) = args.from_list.as_ref().or(args.input.as_ref()) {
+        summary::record_input(path);
+    }
+
+    let files = match (&args.from_list, &args.input) {
+        (Some(list), _) if list.as_os_str() == "-" => collect_list_files(std::io::stdin().lock()),
+        (Some(list), _) => {
+            let file = File::open(list).map_err(|e| {
+                CliError::io(format!("failed to open file list {}", list.display()), e)
+            })?;
+            collect_list_files(BufReader::new(file))
+        }
+        (None, Some(input)) => {
+            #[cfg(feature = "zip")]
+            if has_extension(input, "zip") {
+                return collect_zip_files(input);
+            }
+
+            #[cfg(feature = "tar")]
+            if has_extension(input, "tar") {
+                return collect_tar_files(input);
+            }
+
+            collect_input_files(input)
+        }
+        (None, None) => Err(CliError::InvalidArgument(
+            "either `--input` or `--from-list` is required".to_string(),
+        )),
+    }?;
+
+    Ok(files
+        .into_iter()
+        .map(|(abs_path, rel_path, name_hash)| (InputSource::File(abs_path), rel_path, name_hash))
+        .collect())
+}
+
+/// Checks whether `path` is a file with the given extension, ignoring case.
+#[cfg(any(feature = "zip", feature = "tar"))]
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Reads every file in a `.zip` archive into memory, hashed by its path inside the zip.
+#[cfg(feature = "zip")]
+fn collect_zip_files(input: &Path) -> Result<Vec<(InputSource, PathBuf, AfsHash)>, CliError> {
+    let file = File::open(input)
+        .map_err(|e| CliError::io(format!("failed to open zip file {}", input.display()), e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| {
+        CliError::Archive(format!("failed to read zip file {}: {e}", input.display()))
+    })?;
+
+    let mut files = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| CliError::Archive(format!("failed to read zip entry {index}: {e}")))?;
+        if !entry.is_file() {
+            continue;
+        }
+
+        // `enclosed_name` rejects absolute paths and `..` components
+        let rel_path = entry.enclosed_name().ok_or_else(|| {
+            CliError::Archive(format!("zip entry `{}` has an unsafe path", entry.name()))
+        })?;
+        if rel_path.file_name().is_some_and(|name| name == ".time") {
+            log_info(&format!("Skipping .time file: {}", rel_path.display()));
+            continue;
+        }
+
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data).map_err(|e| {
+            CliError::io(
+                format!("failed to read zip entry {}", rel_path.display()),
+                e,
+            )
+        })?;
+
+        let name_hash = input_name_hash(&rel_path.to_string_lossy())?;
+        files.push((InputSource::Memory(data), rel_path, name_hash));
     }
 
+    Ok(files)
+}
+
+/// Reads every file in a `.tar` archive into memory, hashed by its path inside the tar.
+#[cfg(feature = "tar")]
+fn collect_tar_files(input: &Path) -> Result<Vec<(InputSource, PathBuf, AfsHash)>, CliError> {
+    let read_err =
+        |e: std::io::Error| CliError::io(format!("failed to read tar file {}", input.display()), e);
+
+    let file = File::open(input).map_err(read_err)?;
+    let mut archive = tar::Archive::new(file);
+
     let mut files = Vec::new();
-    let walker = walkdir::WalkDir::new(input).into_iter();
+    for entry in archive.entries().map_err(read_err)? {
+        let mut entry = entry.map_err(read_err)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
 
-    for entry in walker {
-        let entry = entry.map_err(|e| format!("failed to read input folder: {e}"))?;
-        if !entry.file_type().is_file() {
+        let rel_path = entry.path().map_err(read_err)?.into_owned();
+        let is_safe = rel_path
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)));
+        if !is_safe {
+            return Err(CliError::Archive(format!(
+                "tar entry `{}` has an unsafe path",
+                rel_path.display()
+            )));
+        }
+        if rel_path.file_name().is_some_and(|name| name == ".time") {
+            log_info(&format!("Skipping .time file: {}", rel_path.display()));
             continue;
         }
 
-        // If the filename is `.time`, ignore it.
-        if entry.file_name() == ".time" {
-            println!("Skipping .time file: {}", entry.path().display());
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data).map_err(|e| {
+            CliError::io(
+                format!("failed to read tar entry {}", rel_path.display()),
+                e,
+            )
+        })?;
+
+        let name_hash = input_name_hash(&rel_path.to_string_lossy())?;
+        files.push((InputSource::Memory(data), rel_path, name_hash));
+    }
+
+    Ok(files)
+}
+
+/// Parses a newline-delimited list of `source_path<TAB>archive_name` pairs.
+///
+/// Each entry is hashed under its archive name rather than its on-disk path, and
+/// every source file is checked to exist before anything gets written.
+pub fn collect_list_files(
+    reader: impl BufRead,
+) -> Result<Vec<(PathBuf, PathBuf, AfsHash)>, CliError> {
+    let mut files = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| CliError::io("failed to read file list", e))?;
+        if line.trim().is_empty() {
             continue;
         }
 
-        let abs_path = entry.path().to_path_buf();
-        let rel_path = entry
-            .path()
-            .strip_prefix(input)
-            .map_err(|e| format!("failed to get relative path: {e}"))?
-            .to_path_buf();
+        let (source, name) = line.split_once('\t').ok_or_else(|| {
+            format!(
+                "file list line {}: expected `source_path<TAB>archive_name`",
+                index + 1
+            )
+        })?;
 
-        let raw_path_str = rel_path.to_string_lossy().to_string();
-        let name_hash =
-            if raw_path_str.len() == 8 && raw_path_str.chars().all(|c| c.is_ascii_hexdigit()) {
-                let hash_val = hex::decode(&raw_path_str)
-                    .map_err(|e| format!("invalid hex in filename '{}': {e}", raw_path_str))?;
-                let bytes: [u8; 4] = hash_val
-                    .as_slice()
-                    .try_into()
-                    .map_err(|_| format!("invalid hash bytes length for '{}'", raw_path_str))?;
-                hdk_secure::hash::AfsHash(i32::from_be_bytes(bytes))
-            } else {
-                let clean_path = raw_path_str.to_lowercase().replace("\\", "/");
-                hdk_secure::hash::AfsHash::new_from_str(&clean_path)
-            };
+        let source = PathBuf::from(source);
+        if !source.is_file() {
+            return Err(CliError::InvalidArgument(format!(
+                "file list line {}: source file `{}` does not exist",
+                index + 1,
+                source.display()
+            )));
+        }
 
-        files.push((abs_path, rel_path, name_hash));
+        let name_hash = input_name_hash(name)?;
+        files.push((source, PathBuf::from(name), name_hash));
     }
 
     Ok(files)
 }
 
+/// Drops files whose name hash collides with an earlier file, keeping the first.
+///
+/// Every collision is listed, and with `strict` set they fail the build instead
+/// of being skipped.
+pub fn check_hash_collisions<S>(
+    files: &mut Vec<(S, PathBuf, AfsHash)>,
+    strict: bool,
+) -> Result<(), CliError> {
+    let mut seen: HashMap<i32, PathBuf> = HashMap::new();
+    let mut collisions = Vec::new();
+
+    files.retain(|(_, rel_path, name_hash)| match seen.entry(name_hash.0) {
+        Entry::Occupied(kept) => {
+            collisions.push(format!(
+                "{} collides with {} (hash: {})",
+                rel_path.display(),
+                kept.get().display(),
+                name_hash
+            ));
+            false
+        }
+        Entry::Vacant(slot) => {
+            slot.insert(rel_path.clone());
+            true
+        }
+    });
+
+    if collisions.is_empty() {
+        return Ok(());
+    }
+
+    if strict {
+        return Err(CliError::InvalidArgument(format!(
+            "{} name hash collision(s):\n  {}",
+            collisions.len(),
+            collisions.join("\n  ")
+        )));
+    }
+
+    for collision in &collisions {
+        log_info(&format!("Warning: {collision}; keeping the first file."));
+    }
+    Ok(())
+}
+
+/// Collects all files in a directory (recursively) or returns a single file.
+///
+/// Calculates and returns the `AfsHash` for each file so callers get a well-formed
+/// (absolute path, relative path, name-hash) tuple.
+pub fn collect_input_files(input: &Path) -> Result<Vec<(PathBuf, PathBuf, AfsHash)>, CliError> {
+    if input.is_file() {
+        let file_name = input
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("file"));
+
+        let name_hash = input_name_hash(&file_name.to_string_lossy())?;
+
+        return Ok(vec![(input.to_path_buf(), file_name, name_hash)]);
+    }
+
+    if !input.is_dir() {
+        return Err(CliError::InvalidArgument(format!(
+            "Input path does not exist: {}",
+            input.display()
+        )));
+    }
+
+    let mut files = Vec::new();
+    // Walk in name order, so files that share a name hash always come out the same way
+    let walker = walkdir::WalkDir::new(input).sort_by_file_name().into_iter();
+
+    for entry in walker {
+        let entry = entry.map_err(|e| CliError::io("failed to read input folder", e.into()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        // If the filename is `.time`, ignore it.
+        if entry.file_name() == ".time" {
+            log_info(&format!("Skipping .time file: {}", entry.path().display()));
+            continue;
+        }
+
+        let abs_path = entry.path().to_path_buf();
+        let rel_path = entry
+            .path()
+            .strip_prefix(input)
+            .map_err(|e| format!("failed to get relative path: {e}"))?
+            .to_path_buf();
+
+        let name_hash = input_name_hash(&rel_path.to_string_lossy())?;
+
+        files.push((abs_path, rel_path, name_hash));
+    }
+
+    Ok(files)
+}
+
+/// Loads a newline-delimited list of archive paths, skipping blank lines and `#` comments.
+pub fn load_name_list(path: &Path) -> Result<Vec<String>, CliError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| CliError::io(format!("failed to read names file {}", path.display()), e))?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Path of the names file written next to `archive` by `bar create --write-names`.
+pub fn sidecar_names_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".names");
+    PathBuf::from(path)
+}
+
+/// Points `--names` at the input archive's sidecar names file, if it has one and
+/// no names file was given.
+pub fn use_sidecar_names(args: &mut ExtractArgs) {
+    if args.names.is_some() || is_stdio(&args.io.input) {
+        return;
+    }
+
+    let path = sidecar_names_path(&args.io.input);
+    if path.is_file() {
+        log_info(&format!("Using file names from {}", path.display()));
+        args.names = Some(path);
+    }
+}
+
+/// Archive path of the optional entry listing the original names of all other entries.
+pub const EMBEDDED_NAMES_PATH: &str = ".hdk-names";
+
+/// Builds the embedded names entry: a newline-delimited list of archive paths, in the
+/// same format as a `--names` file. Entries that only have a raw hash are left out.
+pub fn encode_name_list<S>(files: &[(S, PathBuf, AfsHash)]) -> Vec<u8> {
+    files
+        .iter()
+        .map(|(_, rel_path, _)| rel_path.to_string_lossy().replace('\\', "/"))
+        .filter(|name| !is_hash_name(name))
+        .map(|name| name + "\n")
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Parses an embedded names entry into a map from name hash to relative output path.
+pub fn decode_name_list(data: &[u8]) -> HashMap<i32, PathBuf> {
+    let names: Vec<String> = String::from_utf8_lossy(data)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    resolve_names(&names)
+}
+
+/// Maps archive paths to their name hashes.
+///
+/// Names that could escape the output folder (absolute paths, `..`) are skipped.
+fn resolve_names(names: &[String]) -> HashMap<i32, PathBuf> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let path = PathBuf::from(name);
+            if path
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+            {
+                Some((hash_path(name).0, path))
+            } else {
+                log_info(&format!("Warning: ignoring unsafe name `{name}`"));
+                None
+            }
+        })
+        .collect()
+}
+
+/// An entry of an archive being appended to.
+pub enum AppendEntry<'a> {
+    /// An entry kept from the existing archive
+    Existing(&'a hdk_archive::structs::Entry),
+    /// A file from the `--add` folder
+    New {
+        path: PathBuf,
+        rel_path: PathBuf,
+        name_hash: AfsHash,
+    },
+}
+
+impl AppendEntry<'_> {
+    pub const fn name_hash(&self) -> AfsHash {
+        match self {
+            Self::Existing(entry) => entry.name_hash,
+            Self::New { name_hash, .. } => *name_hash,
+        }
+    }
+}
+
+/// Merges the files of `--add` into an archive's `existing` entries, sorted by name hash.
+///
+/// Files whose hash is already in the archive are rejected unless `--replace` is
+/// given, in which case they supersede the existing entry.
+pub fn plan_append<'a>(
+    args: &AppendArgs,
+    existing: &'a [hdk_archive::structs::Entry],
+) -> Result<Vec<AppendEntry<'a>>, CliError> {
+    summary::record_input(&args.add);
+    if !args.add.is_dir() {
+        return Err(CliError::InvalidArgument(format!(
+            "{} is not a folder",
+            args.add.display()
+        )));
+    }
+
+    let files = collect_input_files(&args.add)?;
+    let added: HashSet<i32> = files.iter().map(|(_, _, hash)| hash.0).collect();
+
+    let duplicates: Vec<_> = existing
+        .iter()
+        .filter(|entry| added.contains(&entry.name_hash.0))
+        .map(|entry| entry.name_hash.to_string())
+        .collect();
+    if !duplicates.is_empty() && !args.replace {
+        return Err(CliError::InvalidArgument(format!(
+            "{} files are already in the archive ({}); pass `--replace` to overwrite them",
+            duplicates.len(),
+            duplicates.join(", ")
+        )));
+    }
+
+    let mut entries: Vec<_> = existing
+        .iter()
+        .filter(|entry| !added.contains(&entry.name_hash.0))
+        .map(AppendEntry::Existing)
+        .chain(
+            files
+                .into_iter()
+                .map(|(path, rel_path, name_hash)| AppendEntry::New {
+                    path,
+                    rel_path,
+                    name_hash,
+                }),
+        )
+        .collect();
+    entries.sort_by_key(|entry| entry.name_hash().0);
+
+    log_info(&format!(
+        "Adding {} files ({} replacing existing entries)",
+        added.len(),
+        duplicates.len()
+    ));
+    Ok(entries)
+}
+
+/// Resolves `--hash` values and `--pattern` globs to the set of entries to act on.
+///
+/// Patterns are matched case-insensitively against the paths of the `names` list,
+/// which are then hashed. Hashes and patterns that don't match any entry present
+/// in the archive are reported as warnings.
+pub fn select_entries(
+    present: &[AfsHash],
+    hashes: &[AfsHash],
+    patterns: &[String],
+    names: Option<&Path>,
+) -> Result<HashSet<i32>, CliError> {
+    let present: HashSet<i32> = present.iter().map(|hash| hash.0).collect();
+    let mut selected = HashSet::new();
+
+    for hash in hashes {
+        if present.contains(&hash.0) {
+            selected.insert(hash.0);
+        } else {
+            log_info(&format!(
+                "Warning: hash {hash} is not present in the archive"
+            ));
+        }
+    }
+
+    if patterns.is_empty() {
+        return Ok(selected);
+    }
+
+    let names = load_name_list(names.ok_or_else(|| {
+        CliError::InvalidArgument("`--pattern` requires a names list (`--names`)".to_string())
+    })?)?;
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+
+    for raw in patterns {
+        let pattern = glob::Pattern::new(raw)
+            .map_err(|e| CliError::InvalidArgument(format!("invalid pattern '{raw}': {e}")))?;
+
+        let mut matched = false;
+        for name in names
+            .iter()
+            .filter(|name| pattern.matches_with(name, options))
+        {
+            let hash = hash_path(name);
+            if present.contains(&hash.0) {
+                selected.insert(hash.0);
+                matched = true;
+            }
+        }
+
+        if !matched {
+            log_info(&format!("Warning: pattern `{raw}` matched no entries"));
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Human-readable name of an endianness, for messages.
+pub const fn endian_name(endian: Endian) -> &'static str {
+    match endian {
+        Endian::Little => "little-endian",
+        Endian::Big => "big-endian",
+    }
+}
+
+/// Detects an archive's endianness from its magic value.
+pub fn archive_endian(data: &[u8]) -> Result<Endian, CliError> {
+    let magic: [u8; 4] = data
+        .get(0..4)
+        .ok_or_else(|| CliError::Archive("File too small to be a valid archive".to_string()))?
+        .try_into()
+        .unwrap();
+
+    magic::magic_to_endianess(&magic)
+        .map(Into::into)
+        .ok_or_else(|| {
+            CliError::Archive(format!(
+                "not an archive: magic is {}, expected E117EFAD (little-endian) or ADEF17E1 (big-endian)",
+                hex::encode_upper(magic)
+            ))
+        })
+}
+
+/// Checks that the archive header declares the `expected` format.
+///
+/// This turns pointing e.g. `sharc extract` at a BAR into a clear error, rather
+/// than an opaque parse failure.
+fn check_archive_version(data: &[u8], expected: ArchiveVersion) -> Result<(), CliError> {
+    let name = |version: ArchiveVersion| {
+        if version == ArchiveVersion::SHARC {
+            "SHARC"
+        } else {
+            "BAR"
+        }
+    };
+
+    match magic::extract_version(data) {
+        Some(version) if version == expected => Ok(()),
+        Some(version) => Err(CliError::Archive(format!(
+            "archive is a {}, but a {} was expected",
+            name(version),
+            name(expected)
+        ))),
+        None => Err(CliError::Archive(format!(
+            "archive header has an unknown version, expected a {}",
+            name(expected)
+        ))),
+    }
+}
+
+/// Checks that every entry the archive declares fits within its `len` bytes.
+///
+/// This catches incomplete downloads up front, instead of failing halfway through
+/// an extraction.
+pub fn check_truncated(
+    entries: &[hdk_archive::structs::Entry],
+    len: usize,
+) -> Result<(), CliError> {
+    let expected = entries
+        .iter()
+        .map(|entry| u64::from(entry.location.0) + u64::from(entry.compressed_size))
+        .max()
+        .unwrap_or_default();
+
+    if expected > len as u64 {
+        return Err(CliError::Archive(format!(
+            "archive appears truncated (expected {expected} bytes, file is {len})"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Narrows an archive's entries down to the one at `--entry-index`, if given.
+pub fn entries_at_index<T>(entries: &[T], index: Option<usize>) -> Result<&[T], CliError> {
+    let Some(index) = index else {
+        return Ok(entries);
+    };
+
+    if index >= entries.len() {
+        return Err(CliError::InvalidArgument(format!(
+            "entry index {index} is out of range (the archive has {} entries)",
+            entries.len()
+        )));
+    }
+
+    Ok(&entries[index..=index])
+}
+
+/// Picks the entries an extraction should write: the `--entry-index` entry, if
+/// given, narrowed down to those matching at least one `--filter`.
+///
+/// A filter matches an entry if it's a prefix of the entry's name hash, or if it
+/// glob-matches the entry's path from the `--names` list. Each entry comes with its
+/// index in the archive's entry table, which `--naming index` names it after.
+pub fn extract_selection<'e>(
+    args: &ExtractArgs,
+    entries: &'e [hdk_archive::structs::Entry],
+) -> Result<Vec<(usize, &'e hdk_archive::structs::Entry)>, CliError> {
+    let first_index = args.entry_index.unwrap_or_default();
+    let entries = entries_at_index(entries, args.entry_index)?;
+    let indexed = entries
+        .iter()
+        .enumerate()
+        .map(|(position, entry)| (first_index + position, entry));
+    if args.filters.is_empty() {
+        return Ok(indexed.collect());
+    }
+
+    let patterns = args
+        .filters
+        .iter()
+        .map(|raw| {
+            glob::Pattern::new(raw)
+                .map(|pattern| (raw.to_ascii_uppercase(), pattern))
+                .map_err(|e| CliError::InvalidArgument(format!("invalid filter '{raw}': {e}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let names = match &args.names {
+        Some(path) => resolve_names(&load_name_list(path)?),
+        None => HashMap::new(),
+    };
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+
+    let matched: Vec<_> = indexed
+        .filter(|(_, entry)| {
+            let hash = entry.name_hash.to_string().to_ascii_uppercase();
+            let name = names.get(&entry.name_hash.0);
+
+            patterns.iter().any(|(prefix, pattern)| {
+                hash.starts_with(prefix)
+                    || name.is_some_and(|name| pattern.matches_path_with(name, options))
+            })
+        })
+        .collect();
+
+    log_info(&format!(
+        "Filter matched {} of {} entries",
+        matched.len(),
+        entries.len()
+    ));
+    Ok(matched)
+}
+
+/// Parses a SHARC archive from raw bytes, returning it along with its endianness.
+pub fn read_sharc(data: &[u8], key: [u8; 32]) -> Result<(SharcArchive, Endian), CliError> {
+    let endian = archive_endian(data)?;
+    check_archive_version(data, ArchiveVersion::SHARC)?;
+    let mut reader = std::io::Cursor::new(data);
+
+    let sharc = match endian {
+        Endian::Little => SharcArchive::read_le_args(&mut reader, (key, data.len() as u32)),
+        Endian::Big => SharcArchive::read_be_args(&mut reader, (key, data.len() as u32)),
+    }
+    .map_err(|e| {
+        CliError::Archive(format!(
+            "failed to read SHARC archive (read as {}, per its magic; is the key correct?): {e}",
+            endian_name(endian)
+        ))
+    })?;
+    check_truncated(&sharc.entries, data.len())?;
+
+    Ok((sharc, endian))
+}
+
+/// Parses a BAR archive from raw bytes, returning it along with its endianness.
+pub fn read_bar(data: &[u8]) -> Result<(BarArchive, Endian), CliError> {
+    let endian = archive_endian(data)?;
+    check_archive_version(data, ArchiveVersion::BAR)?;
+    let mut reader = std::io::Cursor::new(data);
+    let args = (BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY, data.len() as u32);
+
+    let bar = match endian {
+        Endian::Little => BarArchive::read_le_args(&mut reader, args),
+        Endian::Big => BarArchive::read_be_args(&mut reader, args),
+    }
+    .map_err(|e| {
+        CliError::Archive(format!(
+            "failed to open BAR archive (read as {}, per its magic): {e}",
+            endian_name(endian)
+        ))
+    })?;
+    check_truncated(&bar.entries, data.len())?;
+
+    Ok((bar, endian))
+}
+
+/// Generates the IV used to encrypt a single archive entry.
+///
+/// By default the IV is random. In deterministic mode it is derived from the SHA-1
+/// of the entry's name hash instead, so it doesn't depend on thread scheduling and
+/// repeated builds of the same input produce byte-identical archives.
+pub fn entry_iv(name_hash: AfsHash, deterministic: bool) -> [u8; 8] {
+    let mut iv = [0u8; 8];
+
+    if deterministic {
+        let mut hasher = sha1_smol::Sha1::new();
+        hasher.update(&name_hash.0.to_be_bytes());
+        iv.copy_from_slice(&hasher.digest().bytes()[..8]);
+    } else {
+        let mut rng = rand::rng();
+        rng.fill(&mut iv);
+    }
+
+    iv
+}
+
+/// The name `--compression` and `--manifest-in` use for a compression type.
+pub const fn compression_name(compression: CompressionType) -> &'static str {
+    match compression {
+        CompressionType::None => "none",
+        CompressionType::Compressed => "compressed",
+        CompressionType::Encrypted => "encrypted",
+    }
+}
+
+/// Prints the header of a SHARC or BAR archive, and how many of its entries use each
+/// compression type. This is what `sharc inspect` and `bar inspect` show.
+///
+/// The magic, version, byte order and flags are decoded from the raw header, so they
+/// reflect the file as written rather than what the reader made of it.
+pub fn print_archive_header(data: &[u8], timestamp: i32, entries: &[hdk_archive::structs::Entry]) {
+    let magic = hex::encode_upper(&data[..4]);
+    let version = match magic::extract_version(data) {
+        Some(ArchiveVersion::SHARC) => "SHARC",
+        Some(ArchiveVersion::BAR) => "BAR",
+        _ => "unknown",
+    };
+    let endian = magic::magic_to_endianess(data[..4].try_into().unwrap())
+        .map_or("unknown", |endian| endian_name(endian.into()));
+    let flags = magic::extract_flags(data).unwrap_or_default();
+
+    let compressions = [
+        CompressionType::None,
+        CompressionType::Compressed,
+        CompressionType::Encrypted,
+    ]
+    .map(|compression| {
+        let name = compression_name(compression);
+        let count = entries
+            .iter()
+            .filter(|entry| compression_name(entry.location.1) == name)
+            .count();
+        (name, count)
+    });
+
+    if crate::commands::globals().json {
+        let compressions: serde_json::Map<_, _> = compressions
+            .iter()
+            .map(|(name, count)| (name.to_string(), serde_json::Value::from(*count)))
+            .collect();
+
+        println!(
+            "{}",
+            serde_json::json!({
+                "magic": magic,
+                "version": version,
+                "endianness": endian,
+                "flags": flags,
+                "timestamp": timestamp,
+                "entries": entries.len(),
+                "compression": compressions,
+            })
+        );
+        return;
+    }
+
+    println!("Magic: {magic}");
+    println!("Version: {version}");
+    println!("Endianness: {endian}");
+    println!("Flags: 0x{flags:04X}");
+    println!("Timestamp: {timestamp}");
+    println!("Entry Count: {}", entries.len());
+    println!("\nCompression:");
+    for (name, count) in compressions {
+        println!("  - {name}: {count}");
+    }
+}
+
+/// Creates a progress bar that ticks once per entry, for `len` entries.
+///
+/// The bar is hidden when stdout isn't a terminal, so piped output stays clean,
+/// with `--quiet`, and with `--verbose`, which prints a line per entry instead.
+pub fn progress_bar(len: usize) -> ProgressBar {
+    let globals = crate::commands::globals();
+    if globals.verbose > 0 || globals.quiet || !std::io::stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    #[allow(clippy::literal_string_with_formatting_args)] // indicatif template, not `format!`
+    let style = ProgressStyle::with_template("{bar:40} {pos}/{len} entries ({eta} left)")
+        .unwrap_or_else(|_| ProgressStyle::default_bar());
+
+    ProgressBar::new(len as u64).with_style(style)
+}
+
+/// Prints a command's final result: `text`, or `value` with `--json`.
+///
+/// `--quiet` hides the text, but not the JSON, which was asked for explicitly.
+pub fn print_result(text: &str, value: &serde_json::Value) {
+    let globals = crate::commands::globals();
+    if globals.json {
+        print_line(&value.to_string());
+    } else if !globals.quiet {
+        print_line(text);
+    }
+}
+
+/// Prints a line to stdout, or to stderr if stdout is taken by the command's output.
+fn print_line(line: &str) {
+    if STDOUT_TAKEN.load(Ordering::Relaxed) {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Prints the result of a `create` command, with a line comparing the size of the
+/// recorded entries to that of `output` and how long the command took since `start`.
+pub fn print_created(message: &str, output: &Path, start: std::time::Instant) {
+    const MIB: f64 = 1024.0 * 1024.0;
+
+    let (files, input_bytes) = summary::totals();
+    let output_bytes = std::fs::metadata(output).map_or(0, |m| m.len());
+    let ratio = (input_bytes > 0).then(|| output_bytes as f64 / input_bytes as f64);
+    let elapsed = start.elapsed();
+
+    print_result(
+        &format!(
+            "{message}\n{files} files, {:.2} MiB in -> {:.2} MiB out (ratio {}, elapsed {elapsed:.2?})",
+            input_bytes as f64 / MIB,
+            output_bytes as f64 / MIB,
+            ratio.map_or_else(|| "n/a".to_string(), |ratio| format!("{ratio:.2}")),
+        ),
+        &serde_json::json!({
+            "output": output,
+            "files": files,
+            "input_bytes": input_bytes,
+            "output_bytes": output_bytes,
+            "ratio": ratio,
+            "elapsed_secs": elapsed.as_secs_f64(),
+        }),
+    );
+}
+
+/// Prints a status message or warning, unless `--quiet`.
+pub fn log_info(message: &str) {
+    if !crate::commands::globals().quiet {
+        print_line(message);
+    }
+}
+
+/// Prints a line about a single entry, only with `--verbose`.
+pub fn log_entry(message: &str) {
+    if crate::commands::globals().verbose > 0 {
+        print_line(message);
+    }
+}
+
+/// Runs `op`, printing how long it took with `-vv`.
+pub fn timed<T>(what: impl FnOnce() -> String, op: impl FnOnce() -> T) -> T {
+    if crate::commands::globals().verbose < 2 {
+        return op();
+    }
+
+    let start = std::time::Instant::now();
+    let result = op();
+    print_line(&format!("{} took {:.2?}", what(), start.elapsed()));
+    result
+}
+
+/// Prints the detected core count and the number of threads rayon will use.
+///
+/// Only shown with `--verbose`, at the start of parallel operations.
+#[cfg(feature = "rayon")]
+pub fn report_parallelism() {
+    if crate::commands::globals().verbose == 0 {
+        return;
+    }
+
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    print_line(&format!(
+        "Parallelism: {cores} CPU cores detected, using {} threads",
+        rayon::current_num_threads()
+    ));
+}
+
+/// Sizes rayon's global pool from `--threads`, if given.
+///
+/// Must be called before anything runs in the global pool.
+#[cfg(feature = "rayon")]
+pub fn init_thread_pool() -> Result<(), CliError> {
+    let Some(threads) = crate::commands::globals().threads else {
+        return Ok(());
+    };
+
+    // Rayon treats 0 threads as one per CPU core, which is what `--threads 0` means
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|e| format!("failed to create thread pool for `--threads`: {e}").into())
+}
+
+/// Without the `rayon` feature everything is single-threaded, so `--threads` is ignored.
+#[cfg(not(feature = "rayon"))]
+pub fn init_thread_pool() -> Result<(), CliError> {
+    if crate::commands::globals().threads.is_some() {
+        log_info("Warning: `--threads` has no effect without the `rayon` feature");
+    }
+
+    Ok(())
+}
+
+/// Runs `op` in a dedicated pool of `threads` threads, if given, instead of
+/// rayon's global pool.
+///
+/// `flag` is the option `threads` came from, e.g. `--jobs`.
+#[cfg(feature = "rayon")]
+pub fn with_threads<R: Send>(
+    threads: Option<u16>,
+    flag: &str,
+    op: impl FnOnce() -> R + Send,
+) -> Result<R, CliError> {
+    let Some(threads) = threads else {
+        return Ok(op());
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.into())
+        .build()
+        .map_err(|e| format!("failed to create thread pool for `{flag}`: {e}"))?;
+
+    Ok(pool.install(op))
+}
+
+/// Runs `op`. Without the `rayon` feature everything is single-threaded, so
+/// `threads` is ignored.
+#[cfg(not(feature = "rayon"))]
+pub fn with_threads<R>(
+    threads: Option<u16>,
+    flag: &str,
+    op: impl FnOnce() -> R,
+) -> Result<R, CliError> {
+    if threads.is_some() {
+        log_info(&format!(
+            "Warning: `{flag}` has no effect without the `rayon` feature"
+        ));
+    }
+
+    Ok(op())
+}
+
+/// Compresses one SHARC entry with `compression`.
+///
+/// If compressing makes the entry larger, its compressed size is recorded in
+/// `grown_size`, and with `auto_raw` the entry is stored uncompressed instead.
+pub fn compress_sharc_entry(
+    writer: &SharcBuilder,
+    name_hash: AfsHash,
+    rel_path: PathBuf,
+    data: &[u8],
+    compression: CompressionType,
+    iv: [u8; 8],
+    auto_raw: bool,
+) -> Result<CompressedFile, CliError> {
+    let mut compressed_data = timed(
+        || format!("Compressing {}", rel_path.display()),
+        || writer.compress_data(data, compression, &iv),
+    )
+    .map_err(|e| CliError::Decode(format!("failed to compress {}: {e}", rel_path.display())))?;
+    let mut compression = compression;
+    let mut grown_size = None;
+
+    if compression != CompressionType::None && compressed_data.len() > data.len() {
+        grown_size = Some(compressed_data.len());
+
+        if auto_raw {
+            compressed_data = writer
+                .compress_data(data, CompressionType::None, &iv)
+                .map_err(|e| {
+                    CliError::Decode(format!("failed to store {}: {e}", rel_path.display()))
+                })?;
+            compression = CompressionType::None;
+        }
+    }
+
+    Ok(CompressedFile {
+        name_hash,
+        rel_path,
+        uncompressed_size: data.len(),
+        compressed_data,
+        compression,
+        iv,
+        grown_size,
+    })
+}
+
+/// Under `--verbose`, lists the entries that grew after compression as
+/// `(archive name, original size, compressed size)`.
+pub fn report_grown_entries(grown: &[(&Path, usize, usize)], auto_raw: bool) {
+    if grown.is_empty() {
+        return;
+    }
+
+    log_entry(&format!("{} entries grew after compression:", grown.len()));
+    for (name, size, compressed_size) in grown {
+        log_entry(&format!(
+            "  {} ({size} -> {compressed_size} bytes)",
+            name.display()
+        ));
+    }
+
+    if auto_raw {
+        log_entry("These entries were stored uncompressed.");
+    } else {
+        log_entry(
+            "Set their `compression` to `none` with `--manifest-in`, or pass `--auto-raw` to store them uncompressed.",
+        );
+    }
+}
+
+/// An archive's contents, memory-mapped when the `memmap2` feature is enabled.
+///
+/// Entry readers borrow from this, so it must be kept alive until extraction is done.
+pub enum ArchiveBytes {
+    #[cfg(feature = "memmap2")]
+    Mapped(memmap2::Mmap),
+    Read(Vec<u8>),
+}
+
+impl std::ops::Deref for ArchiveBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "memmap2")]
+            Self::Mapped(map) => map,
+            Self::Read(data) => data,
+        }
+    }
+}
+
+impl AsRef<[u8]> for ArchiveBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Opens an archive for reading, mapping it into memory if possible and
+/// falling back to reading it whole otherwise.
+///
+/// `-` reads the archive from stdin. The readers need to seek, so piped input
+/// can't be streamed: the whole archive is held in memory instead.
+pub fn read_archive(path: &Path) -> Result<ArchiveBytes, CliError> {
+    if is_stdio(path) {
+        return read_input(path).map(ArchiveBytes::Read);
+    }
+
+    #[cfg(feature = "memmap2")]
+    {
+        let file = File::open(path).map_err(|e| {
+            CliError::io(format!("failed to open archive file {}", path.display()), e)
+        })?;
+
+        // Safety: the map is read-only; as with any mapped file, the archive
+        // mustn't be modified by another process while it's being read.
+        match unsafe { memmap2::Mmap::map(&file) } {
+            Ok(map) => return Ok(ArchiveBytes::Mapped(map)),
+            Err(e) => log_entry(&format!(
+                "failed to memory-map {}, reading it instead: {e}",
+                path.display()
+            )),
+        }
+    }
+
+    std::fs::read(path)
+        .map(ArchiveBytes::Read)
+        .map_err(|e| CliError::io(format!("failed to read archive file {}", path.display()), e))
+}
+
+/// A SHARC or BAR archive opened for reading, unwrapped first if it was inside an SDAT.
+pub struct OpenArchive {
+    data: ArchiveBytes,
+    kind: ArchiveKind,
+}
+
+enum ArchiveKind {
+    Sharc(SharcArchive),
+    Bar(BarArchive),
+}
+
+impl OpenArchive {
+    pub fn open(path: &Path) -> Result<Self, CliError> {
+        Self::from_bytes(read_archive(path)?, path)
+    }
+
+    /// Opens an archive that's already in memory; `path` is only used in error messages.
+    pub fn from_bytes(mut data: ArchiveBytes, path: &Path) -> Result<Self, CliError> {
+        // SHARCs inside SDATs are encrypted with their own key
+        let wrapped = magic::sdat_matcher(&data);
+        if wrapped {
+            let decrypted = hdk_sdat::SdatReader::open(std::io::Cursor::new(&*data), &SDAT_KEYS)
+                .map_err(|e| CliError::Archive(format!("failed to open SDAT: {e}")))?
+                .decrypt_to_vec()
+                .map_err(|e| CliError::Archive(format!("failed to decrypt SDAT: {e}")))?;
+            data = ArchiveBytes::Read(decrypted);
+        }
+
+        let kind = match magic::get_matcher().get(&data).map(|kind| kind.mime_type()) {
+            Some(kind) if kind == magic::MIME_SHARC.0 => {
+                let key = if wrapped {
+                    SHARC_SDAT_KEY
+                } else {
+                    SHARC_DEFAULT_KEY
+                };
+                ArchiveKind::Sharc(read_sharc(&data, key)?.0)
+            }
+            Some(kind) if kind == magic::MIME_BAR.0 => ArchiveKind::Bar(read_bar(&data)?.0),
+            _ => {
+                return Err(CliError::Archive(format!(
+                    "{} is not a SHARC or BAR archive",
+                    path.display()
+                )));
+            }
+        };
+
+        Ok(Self { data, kind })
+    }
+
+    pub fn entries(&self) -> &[hdk_archive::structs::Entry] {
+        match &self.kind {
+            ArchiveKind::Sharc(sharc) => &sharc.entries,
+            ArchiveKind::Bar(bar) => &bar.entries,
+        }
+    }
+
+    pub fn into_entries(self) -> Vec<hdk_archive::structs::Entry> {
+        match self.kind {
+            ArchiveKind::Sharc(sharc) => sharc.entries,
+            ArchiveKind::Bar(bar) => bar.entries,
+        }
+    }
+
+    /// Decompresses and decrypts a single entry.
+    pub fn entry_data(&self, entry: &hdk_archive::structs::Entry) -> Result<Vec<u8>, CliError> {
+        // Each caller gets its own view of the data, so entries can be read in parallel
+        let mut reader = std::io::Cursor::new(&self.data);
+        match &self.kind {
+            ArchiveKind::Sharc(sharc) => sharc.entry_data(&mut reader, entry),
+            ArchiveKind::Bar(bar) => {
+                bar.entry_data(&mut reader, entry, &BAR_DEFAULT_KEY, &BAR_SIGNATURE_KEY)
+            }
+        }
+        .map_err(|e| CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash)))
+    }
+}
+
+/// With `--check`, compares the size of a decoded entry against the one in its header.
+///
+/// SHARC and BAR entries carry no checksum, so their declared size is all there is to
+/// check. A mismatch is reported as an error, but doesn't stop the extraction.
+pub fn check_entry(args: &ExtractArgs, entry: &hdk_archive::structs::Entry, data: &[u8]) {
+    if args.check && data.len() != entry.uncompressed_size as usize {
+        crate::commands::report_error(&CliError::Decode(format!(
+            "{}: decoded to {} bytes, expected {}",
+            entry.name_hash,
+            data.len(),
+            entry.uncompressed_size
+        )));
+    }
+}
+
+/// Decides what an entry that failed to decode does to the extraction.
+///
+/// With `--fail-fast` the error stops it. Otherwise the error is reported and the entry
+/// skipped, so the rest of a damaged archive is still extracted, and
+/// [`check_failed_entries`] fails the command once everything else is written.
+pub fn salvage_entry<T>(
+    args: &ExtractArgs,
+    result: Result<T, CliError>,
+) -> Result<Option<T>, CliError> {
+    match result {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if args.fail_fast => Err(e),
+        Err(e) => {
+            crate::commands::report_error(&e);
+            Ok(None)
+        }
+    }
+}
+
+/// Fails an extraction that skipped entries with [`salvage_entry`], listing their hashes.
+pub fn check_failed_entries(total: usize, mut failed: Vec<AfsHash>) -> Result<(), CliError> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    failed.sort_by_key(|hash| hash.0);
+    let hashes: Vec<_> = failed.iter().map(ToString::to_string).collect();
+    Err(CliError::Decode(format!(
+        "extracted {} of {total} entries, {} failed to decode: {}",
+        total - failed.len(),
+        failed.len(),
+        hashes.join(", ")
+    )))
+}
+
+/// With `--recursive`, extracts the entry written to `path` into a sibling folder if
+/// it's itself a SHARC, BAR or SDAT archive, and so on for its own entries.
+///
+/// Nested archives that can't be opened or decoded, e.g. because they use a key other
+/// than the built-in one, are skipped with a warning rather than failing the extraction.
+pub fn extract_nested(args: &ExtractArgs, path: &Path, data: &[u8]) -> Result<(), CliError> {
+    if args.recursive {
+        extract_nested_at(args, path, data, 1)?;
+    }
+    Ok(())
+}
+
+fn extract_nested_at(
+    args: &ExtractArgs,
+    path: &Path,
+    data: &[u8],
+    depth: u8,
+) -> Result<(), CliError> {
+    let is_archive = magic::sdat_matcher(data)
+        || magic::get_matcher().get(data).is_some_and(|kind| {
+            kind.mime_type() == magic::MIME_SHARC.0 || kind.mime_type() == magic::MIME_BAR.0
+        });
+    if !is_archive {
+        return Ok(());
+    }
+
+    if depth > args.max_depth {
+        log_info(&format!(
+            "Warning: not extracting nested archive {}: --max-depth {} reached",
+            path.display(),
+            args.max_depth
+        ));
+        return Ok(());
+    }
+
+    let archive = match OpenArchive::from_bytes(ArchiveBytes::Read(data.to_vec()), path) {
+        Ok(archive) => archive,
+        Err(e) => {
+            log_info(&format!(
+                "Warning: skipping nested archive {}: {e}",
+                path.display()
+            ));
+            return Ok(());
+        }
+    };
+
+    let mut dir_name = path.file_name().unwrap_or_default().to_os_string();
+    dir_name.push("_extracted");
+    let dir = path.with_file_name(dir_name);
+
+    for entry in archive.entries() {
+        let entry_data = match archive.entry_data(entry) {
+            Ok(entry_data) => entry_data,
+            Err(e) => {
+                log_info(&format!(
+                    "Warning: skipping nested archive {}: {e}",
+                    path.display()
+                ));
+                return Ok(());
+            }
+        };
+
+        let entry_path = dir.join(entry.name_hash.to_string());
+        write_extracted_file(&entry_path, &entry_data, None, args.overwrite_policy())?;
+        extract_nested_at(args, &entry_path, &entry_data, depth + 1)?;
+    }
+
+    log_entry(&format!(
+        "Extracted nested archive {} to {}",
+        path.display(),
+        dir.display()
+    ));
+    Ok(())
+}
+
+/// Reads the entry table of a SHARC or BAR archive, unwrapping it first if it's inside an SDAT.
+pub fn read_archive_entries(path: &Path) -> Result<Vec<hdk_archive::structs::Entry>, CliError> {
+    OpenArchive::open(path).map(OpenArchive::into_entries)
+}
+
 /// Reads a file into a byte vector.
 pub fn read_file_bytes(path: &Path) -> Result<SmallVec<[u8; 16_384]>, std::io::Error> {
     let mut file = File::open(path)?;
@@ -1,42 +1,55 @@
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 
-use crate::commands::{Execute, common};
+use crate::{
+    commands::{BatchIOArgs, Execute, common, summary},
+    error::CliError,
+    magic,
+};
 use clap::{Subcommand, ValueEnum};
 
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
 #[derive(Subcommand, Debug)]
 #[clap(alias = "comp")]
+#[allow(clippy::enum_variant_names)] // `compress compress` is the established command name
 pub enum Compress {
     /// Compress a file using EdgeZLib or EdgeLZMA
     #[clap(alias = "c")]
     Compress {
-        /// Input file path
-        #[clap(short, long)]
-        input: PathBuf,
-
-        /// Output file path
-        #[clap(short, long)]
-        output: PathBuf,
+        #[clap(flatten)]
+        io: BatchIOArgs,
 
         /// Compression algorithm to use
         #[clap(short, long, value_enum, default_value_t = Algorithm::Lzma)]
         algorithm: Algorithm,
+
+        /// Compress even if the input already looks compressed
+        #[clap(long, default_value_t = false)]
+        force: bool,
     },
     /// Decompress a file compressed with EdgeZLib or EdgeLZMA
     #[clap(alias = "d")]
     Decompress {
-        /// Input file path
-        #[clap(short, long)]
-        input: PathBuf,
+        #[clap(flatten)]
+        io: BatchIOArgs,
 
-        /// Output file path
+        /// Compression algorithm that was used (detected from the input if not given)
+        #[clap(short, long, value_enum)]
+        algorithm: Option<Algorithm>,
+    },
+    /// Print the segment sizes of an EdgeZLib or EdgeLZMA stream, without decompressing it
+    #[clap(alias = "i")]
+    Info {
+        /// Input file path, or `-` for stdin
         #[clap(short, long)]
-        output: PathBuf,
+        input: PathBuf,
 
-        /// Compression algorithm that was used
-        #[clap(short, long, value_enum, default_value_t = Algorithm::Lzma)]
-        algorithm: Algorithm,
+        /// Compression algorithm that was used (EdgeLZMA is detected; EdgeZLib has to be given)
+        #[clap(short, long, value_enum)]
+        algorithm: Option<Algorithm>,
     },
 }
 
@@ -51,107 +64,294 @@ pub enum Algorithm {
     Lzma,
 }
 
+impl Algorithm {
+    /// Extension of files compressed with this algorithm in a batch.
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Zlib => "zlib",
+            Self::Lzma => "lzma",
+        }
+    }
+}
+
 impl Execute for Compress {
-    fn execute(self) {
-        let result = match self {
+    fn execute(self) -> Result<(), CliError> {
+        match self {
             Self::Compress {
-                input,
-                output,
-                algorithm,
-            } => compress(&input, &output, algorithm),
-            Self::Decompress {
-                input,
-                output,
+                io,
                 algorithm,
-            } => decompress(&input, &output, algorithm),
-        };
-
-        if let Err(e) = result {
-            eprintln!("Error: {e}");
+                force,
+            } => run_batch(
+                &io,
+                |input| {
+                    let mut name = input.file_name().unwrap_or_default().to_os_string();
+                    name.push(".");
+                    name.push(algorithm.extension());
+                    PathBuf::from(name)
+                },
+                |input, output| compress(input, output, algorithm, force),
+            ),
+            Self::Decompress { io, algorithm } => run_batch(
+                &io,
+                |input| {
+                    // `a.xml.lzma` becomes `a.xml`; other names are kept as-is
+                    let compressed = input.extension().is_some_and(|ext| {
+                        ext.eq_ignore_ascii_case("lzma") || ext.eq_ignore_ascii_case("zlib")
+                    });
+                    let name = if compressed {
+                        input.file_stem()
+                    } else {
+                        input.file_name()
+                    };
+                    PathBuf::from(name.unwrap_or_default())
+                },
+                |input, output| decompress(input, output, algorithm),
+            ),
+            Self::Info { input, algorithm } => info(&input, algorithm),
         }
     }
 }
 
-fn compress(input: &Path, output: &Path, algorithm: Algorithm) -> Result<(), String> {
-    let input_file = File::open(input).map_err(|e| format!("failed to open input file: {e}"))?;
-    let mut reader = BufReader::new(input_file);
+/// Runs `process` on each input of `io` and its output, in parallel with the
+/// `rayon` feature. In a batch, outputs are named by `output_name`.
+fn run_batch(
+    io: &BatchIOArgs,
+    output_name: impl Fn(&Path) -> PathBuf,
+    process: impl Fn(&Path, &Path) -> Result<(), CliError> + Sync,
+) -> Result<(), CliError> {
+    let pairs = io.resolve(output_name)?;
+
+    if io.is_batch() {
+        std::fs::create_dir_all(&io.output)
+            .map_err(|e| CliError::io("failed to create output folder", e))?;
+        common::confirm_overwrite_all(pairs.iter().map(|pair| pair.output.as_path()))?;
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    let result = pairs
+        .iter()
+        .try_for_each(|pair| process(&pair.input, &pair.output));
+
+    #[cfg(feature = "rayon")]
+    let result = pairs
+        .par_iter()
+        .try_for_each(|pair| process(&pair.input, &pair.output));
+
+    result
+}
+
+fn compress(
+    input: &Path,
+    output: &Path,
+    algorithm: Algorithm,
+    force: bool,
+) -> Result<(), CliError> {
+    summary::record_input(input);
+
+    let mut reader = BufReader::new(common::open_input(input)?);
+
+    // Compressing an already-compressed file only wastes space and time
+    let header = reader
+        .fill_buf()
+        .map_err(|e| CliError::io("failed to read input file", e))?;
+    let already_compressed = magic::get_matcher()
+        .get(header)
+        .is_some_and(|t| t.mime_type() == magic::MIME_EDGE_LZMA.0);
+
+    if already_compressed {
+        if !force {
+            return Err(CliError::InvalidArgument(format!(
+                "{} appears to already be EdgeLZMA-compressed; pass `--force` to compress it anyway",
+                input.display()
+            )));
+        }
+
+        common::log_info(&format!(
+            "Warning: {} appears to already be EdgeLZMA-compressed, compressing anyway.",
+            input.display()
+        ));
+    }
 
-    let output_file = common::create_output_file(output)?;
-    let writer = BufWriter::new(output_file);
+    let writer = BufWriter::new(common::open_output(output)?);
 
-    let bytes_written = match algorithm {
+    let (bytes_read, bytes_written) = match algorithm {
         Algorithm::Zlib => compress_zlib(&mut reader, writer)?,
         Algorithm::Lzma => compress_lzma(&mut reader, writer)?,
     };
 
-    println!(
-        "Compressed {} -> {} ({} bytes, {:?})",
-        input.display(),
-        output.display(),
-        bytes_written,
-        algorithm
+    summary::record_entries(1, bytes_written);
+    common::print_result(
+        &format!(
+            "Compressed {} -> {} ({} -> {} bytes, {:.1}%, {:?})",
+            input.display(),
+            output.display(),
+            bytes_read,
+            bytes_written,
+            ratio(bytes_written, bytes_read),
+            algorithm
+        ),
+        &serde_json::json!({
+            "input": input,
+            "output": output,
+            "bytes_read": bytes_read,
+            "bytes_written": bytes_written,
+            "algorithm": format!("{algorithm:?}"),
+        }),
     );
     Ok(())
 }
 
-fn decompress(input: &Path, output: &Path, algorithm: Algorithm) -> Result<(), String> {
-    let input_file = File::open(input).map_err(|e| format!("failed to open input file: {e}"))?;
-    let reader = BufReader::new(input_file);
+fn decompress(input: &Path, output: &Path, algorithm: Option<Algorithm>) -> Result<(), CliError> {
+    summary::record_input(input);
 
-    let output_file = common::create_output_file(output)?;
-    let mut writer = BufWriter::new(output_file);
+    if common::is_stdio(input) {
+        // EdgeLZMA streams have to be seekable, so stdin is read whole first
+        let data = common::read_input(input)?;
+        return decompress_from(std::io::Cursor::new(data), input, output, algorithm);
+    }
+
+    let input_file = File::open(input).map_err(|e| CliError::io("failed to open input file", e))?;
+    decompress_from(BufReader::new(input_file), input, output, algorithm)
+}
 
-    let bytes_written = match algorithm {
-        Algorithm::Zlib => decompress_zlib(reader, &mut writer)?,
-        Algorithm::Lzma => decompress_lzma(reader, &mut writer)?,
+fn decompress_from<R: BufRead + Seek>(
+    mut reader: R,
+    input: &Path,
+    output: &Path,
+    algorithm: Option<Algorithm>,
+) -> Result<(), CliError> {
+    // EdgeLZMA streams start with a magic; EdgeZLib ones have none, so anything else is
+    // assumed to be EdgeZLib
+    let detected = algorithm.is_none();
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => {
+            let header = reader
+                .fill_buf()
+                .map_err(|e| CliError::io("failed to read input file", e))?;
+            let is_lzma = magic::get_matcher()
+                .get(header)
+                .is_some_and(|t| t.mime_type() == magic::MIME_EDGE_LZMA.0);
+
+            let algorithm = if is_lzma {
+                Algorithm::Lzma
+            } else {
+                Algorithm::Zlib
+            };
+            common::log_info(&format!("Detected {algorithm:?} compression"));
+            algorithm
+        }
     };
 
-    println!(
-        "Decompressed {} -> {} ({} bytes, {:?})",
-        input.display(),
-        output.display(),
-        bytes_written,
-        algorithm
+    let mut writer = BufWriter::new(common::open_output(output)?);
+
+    let result = match algorithm {
+        Algorithm::Zlib => decompress_zlib(reader, &mut writer),
+        Algorithm::Lzma => decompress_lzma(reader, &mut writer),
+    };
+
+    let bytes_written = match result {
+        Ok(bytes) => bytes,
+        Err(e) if detected => {
+            // Don't leave a half-written file behind for a guess that didn't pan out
+            drop(writer);
+            if !common::is_stdio(output) {
+                let _ = std::fs::remove_file(output);
+            }
+            return Err(CliError::Decode(format!(
+                "{e} (the input was assumed to be {algorithm:?}; pass `--algorithm` if it's something else)"
+            )));
+        }
+        Err(e) => return Err(e),
+    };
+
+    summary::record_entries(1, bytes_written);
+    common::print_result(
+        &format!(
+            "Decompressed {} -> {} ({} bytes, {:?})",
+            input.display(),
+            output.display(),
+            bytes_written,
+            algorithm
+        ),
+        &serde_json::json!({
+            "input": input,
+            "output": output,
+            "bytes_written": bytes_written,
+            "algorithm": format!("{algorithm:?}"),
+        }),
     );
     Ok(())
 }
 
+/// `compressed` as a percentage of `original`.
+fn ratio(compressed: u64, original: u64) -> f64 {
+    if original == 0 {
+        return 0.0;
+    }
+
+    compressed as f64 / original as f64 * 100.0
+}
+
+/// Counts the bytes written through it, so the compressors can report their output size.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    const fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Zlib (EdgeZLib segmented)
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn compress_zlib<R: Read, W: Write>(reader: &mut R, writer: W) -> Result<u64, String> {
+/// Returns the number of bytes read and written.
+fn compress_zlib<R: Read, W: Write>(reader: &mut R, writer: W) -> Result<(u64, u64), CliError> {
     use hdk_comp::zlib::writer::SegmentedZlibWriter;
 
-    let mut compressor = SegmentedZlibWriter::new(writer);
+    let mut compressor = SegmentedZlibWriter::new(CountingWriter::new(writer));
 
-    io::copy(reader, &mut compressor).map_err(|e| format!("compression failed: {e}"))?;
+    let bytes_read = io::copy(reader, &mut compressor)
+        .map_err(|e| CliError::Decode(format!("compression failed: {e}")))?;
 
-    let inner = compressor
+    let mut inner = compressor
         .finish()
-        .map_err(|e| format!("failed to finalize compressed stream: {e}"))?;
+        .map_err(|e| CliError::Decode(format!("failed to finalize compressed stream: {e}")))?;
 
-    // Get bytes written (flush first)
-    let mut inner = inner;
     inner
         .flush()
-        .map_err(|e| format!("failed to flush output: {e}"))?;
+        .map_err(|e| CliError::io("failed to flush output", e))?;
 
-    // We don't have direct access to bytes written, so we report success
-    Ok(0) // Caller will stat the file if needed
+    Ok((bytes_read, inner.count))
 }
 
-fn decompress_zlib<R: Read, W: Write>(reader: R, writer: &mut W) -> Result<u64, String> {
+fn decompress_zlib<R: Read, W: Write>(reader: R, writer: &mut W) -> Result<u64, CliError> {
     use hdk_comp::zlib::reader::SegmentedZlibReader;
 
     let mut decompressor = SegmentedZlibReader::new(reader);
 
-    let bytes =
-        io::copy(&mut decompressor, writer).map_err(|e| format!("decompression failed: {e}"))?;
+    let bytes = io::copy(&mut decompressor, writer)
+        .map_err(|e| CliError::Decode(format!("decompression failed: {e}")))?;
 
     writer
         .flush()
-        .map_err(|e| format!("failed to flush output: {e}"))?;
+        .map_err(|e| CliError::io("failed to flush output", e))?;
 
     Ok(bytes)
 }
@@ -160,37 +360,228 @@ fn decompress_zlib<R: Read, W: Write>(reader: R, writer: &mut W) -> Result<u64,
 // LZMA (EdgeLZMA segmented)
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn compress_lzma<R: Read, W: Write>(reader: &mut R, writer: W) -> Result<u64, String> {
+/// Returns the number of bytes read and written.
+fn compress_lzma<R: Read, W: Write>(reader: &mut R, writer: W) -> Result<(u64, u64), CliError> {
     use hdk_comp::lzma::writer::SegmentedLzmaWriter;
 
-    let mut compressor = SegmentedLzmaWriter::new(writer);
+    let mut compressor = SegmentedLzmaWriter::new(CountingWriter::new(writer));
 
-    io::copy(reader, &mut compressor).map_err(|e| format!("compression failed: {e}"))?;
+    let bytes_read = io::copy(reader, &mut compressor)
+        .map_err(|e| CliError::Decode(format!("compression failed: {e}")))?;
 
-    let inner = compressor
+    let mut inner = compressor
         .finish()
-        .map_err(|e| format!("failed to finalize compressed stream: {e}"))?;
+        .map_err(|e| CliError::Decode(format!("failed to finalize compressed stream: {e}")))?;
 
-    let mut inner = inner;
     inner
         .flush()
-        .map_err(|e| format!("failed to flush output: {e}"))?;
+        .map_err(|e| CliError::io("failed to flush output", e))?;
 
-    Ok(0)
+    Ok((bytes_read, inner.count))
 }
 
-fn decompress_lzma<R: Read + Seek, W: Write>(reader: R, writer: &mut W) -> Result<u64, String> {
+fn decompress_lzma<R: Read + Seek, W: Write>(reader: R, writer: &mut W) -> Result<u64, CliError> {
     use hdk_comp::lzma::reader::SegmentedLzmaReader;
 
-    let mut decompressor =
-        SegmentedLzmaReader::new(reader).map_err(|e| format!("failed to open LZMA stream: {e}"))?;
+    let mut decompressor = SegmentedLzmaReader::new(reader)
+        .map_err(|e| CliError::Decode(format!("failed to open LZMA stream: {e}")))?;
 
-    let bytes =
-        io::copy(&mut decompressor, writer).map_err(|e| format!("decompression failed: {e}"))?;
+    let bytes = io::copy(&mut decompressor, writer)
+        .map_err(|e| CliError::Decode(format!("decompression failed: {e}")))?;
 
     writer
         .flush()
-        .map_err(|e| format!("failed to flush output: {e}"))?;
+        .map_err(|e| CliError::io("failed to flush output", e))?;
 
     Ok(bytes)
 }
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Stream info
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Uncompressed size of a full segment, which is stored as 0 since it doesn't fit in a `u16`.
+const FULL_SEGMENT_SIZE: u32 = 0x10000;
+
+/// Length of the EdgeLZMA stream header, before the segment table.
+const LZMA_HEADER_LEN: usize = 16;
+
+/// Sizes of one segment of a compressed stream.
+struct SegmentInfo {
+    compressed_size: u32,
+    uncompressed_size: u32,
+}
+
+fn info(input: &Path, algorithm: Option<Algorithm>) -> Result<(), CliError> {
+    summary::record_input(input);
+
+    let mut reader = BufReader::new(common::open_input(input)?);
+    let header = reader
+        .fill_buf()
+        .map_err(|e| CliError::io("failed to read input file", e))?;
+    let is_lzma = magic::get_matcher()
+        .get(header)
+        .is_some_and(|t| t.mime_type() == magic::MIME_EDGE_LZMA.0);
+
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None if is_lzma => Algorithm::Lzma,
+        None => {
+            return Err(CliError::InvalidArgument(format!(
+                "{} has no EdgeLZMA magic; pass `--algorithm zlib` if it's an EdgeZLib stream",
+                input.display()
+            )));
+        }
+    };
+
+    // Only the segment headers are read; payloads are skipped without being decoded
+    let segments = match algorithm {
+        Algorithm::Zlib => scan_zlib_segments(&mut reader)?,
+        Algorithm::Lzma => scan_lzma_segments(&mut reader)?,
+    };
+
+    let compressed: u64 = segments.iter().map(|s| u64::from(s.compressed_size)).sum();
+    let uncompressed: u64 = segments
+        .iter()
+        .map(|s| u64::from(s.uncompressed_size))
+        .sum();
+    summary::record_entries(segments.len(), uncompressed);
+
+    if crate::commands::globals().json {
+        let segments: Vec<_> = segments
+            .iter()
+            .map(|segment| {
+                serde_json::json!({
+                    "compressed_size": segment.compressed_size,
+                    "uncompressed_size": segment.uncompressed_size,
+                })
+            })
+            .collect();
+
+        println!(
+            "{}",
+            serde_json::json!({
+                "algorithm": format!("{algorithm:?}"),
+                "segments": segments,
+                "compressed_size": compressed,
+                "uncompressed_size": uncompressed,
+                "ratio": ratio(compressed, uncompressed),
+            })
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{:>7}  {:>10}  {:>12}  {:>6}",
+        "segment", "compressed", "uncompressed", "ratio"
+    );
+    for (index, segment) in segments.iter().enumerate() {
+        println!(
+            "{index:>7}  {:>10}  {:>12}  {:>5.1}%",
+            segment.compressed_size,
+            segment.uncompressed_size,
+            ratio(
+                segment.compressed_size.into(),
+                segment.uncompressed_size.into()
+            )
+        );
+    }
+    println!(
+        "{} segments ({algorithm:?}), {compressed} bytes compressed, {uncompressed} bytes uncompressed ({:.1}%)",
+        segments.len(),
+        ratio(compressed, uncompressed)
+    );
+
+    Ok(())
+}
+
+/// Reads a big-endian `u16` at `offset` of `data`.
+fn be_u16(data: &[u8], offset: usize) -> u32 {
+    u16::from_be_bytes([data[offset], data[offset + 1]]).into()
+}
+
+/// Walks an EdgeZLib stream, which is a sequence of segments that each start with
+/// their compressed and uncompressed sizes.
+fn scan_zlib_segments<R: BufRead>(reader: &mut R) -> Result<Vec<SegmentInfo>, CliError> {
+    let mut segments = Vec::new();
+
+    loop {
+        let at_end = reader
+            .fill_buf()
+            .map_err(|e| CliError::io("failed to read input file", e))?
+            .is_empty();
+        if at_end {
+            return Ok(segments);
+        }
+
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).map_err(|e| {
+            CliError::Decode(format!(
+                "truncated EdgeZLib header for segment {}: {e}",
+                segments.len()
+            ))
+        })?;
+
+        let compressed_size = be_u16(&header, 0);
+        let uncompressed_size = match be_u16(&header, 2) {
+            0 => FULL_SEGMENT_SIZE,
+            size => size,
+        };
+
+        let skipped = io::copy(&mut reader.take(compressed_size.into()), &mut io::sink())
+            .map_err(|e| CliError::io("failed to read input file", e))?;
+        if skipped < u64::from(compressed_size) {
+            return Err(CliError::Decode(format!(
+                "EdgeZLib segment {} is truncated: {skipped} of {compressed_size} bytes",
+                segments.len()
+            )));
+        }
+
+        segments.push(SegmentInfo {
+            compressed_size,
+            uncompressed_size,
+        });
+    }
+}
+
+/// Reads the segment table of an EdgeLZMA stream, which follows its 16-byte header.
+fn scan_lzma_segments<R: Read>(reader: &mut R) -> Result<Vec<SegmentInfo>, CliError> {
+    let mut header = [0u8; LZMA_HEADER_LEN];
+    reader
+        .read_exact(&mut header)
+        .map_err(|e| CliError::Decode(format!("truncated EdgeLZMA header: {e}")))?;
+
+    let segment_count = be_u16(&header, 6) as usize;
+    let expected_size = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+
+    let mut table = vec![0u8; segment_count * 8];
+    reader
+        .read_exact(&mut table)
+        .map_err(|e| CliError::Decode(format!("truncated EdgeLZMA segment table: {e}")))?;
+
+    let segments: Vec<SegmentInfo> = table
+        .chunks_exact(8)
+        .map(|entry| SegmentInfo {
+            compressed_size: match be_u16(entry, 0) {
+                0 => FULL_SEGMENT_SIZE,
+                size => size,
+            },
+            uncompressed_size: match be_u16(entry, 2) {
+                0 => FULL_SEGMENT_SIZE,
+                size => size,
+            },
+        })
+        .collect();
+
+    let total: u64 = segments
+        .iter()
+        .map(|s| u64::from(s.uncompressed_size))
+        .sum();
+    if total != u64::from(expected_size) {
+        common::log_info(&format!(
+            "Warning: the segments add up to {total} bytes, but the header says {expected_size}"
+        ));
+    }
+
+    Ok(segments)
+}
@@ -0,0 +1,258 @@
+use std::io::Write;
+
+use binrw::Endian;
+use clap::Args;
+use hdk_archive::{
+    bar::builder::BarBuilder,
+    sharc::builder::SharcBuilder,
+    structs::{ArchiveFlags, ArchiveFlagsValue, ArchiveVersion, CompressionType, Entry},
+};
+use hdk_secure::hash::AfsHash;
+use smallvec::SmallVec;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{
+    commands::{ArchiveType, Execute, IOArgs, common, summary},
+    error::CliError,
+    keys::{self, BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY, KeyArg, SHARC_DEFAULT_KEY, SHARC_FILES_KEY},
+    magic,
+};
+
+#[derive(Args, Debug)]
+pub struct Convert {
+    #[clap(flatten)]
+    pub io: IOArgs,
+
+    /// Format to convert the archive to
+    #[clap(long, value_enum)]
+    pub to: ArchiveType,
+
+    /// Key for the new SHARC archive, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+/// An entry decoded from the input archive.
+struct DecodedEntry {
+    name_hash: AfsHash,
+    compression: CompressionType,
+    data: Vec<u8>,
+}
+
+/// The input archive, decoded.
+struct DecodedArchive {
+    kind: ArchiveType,
+    timestamp: i32,
+    endian: Endian,
+    entries: Vec<DecodedEntry>,
+}
+
+impl Execute for Convert {
+    fn execute(self) -> Result<(), CliError> {
+        self.convert()
+    }
+}
+
+impl Convert {
+    fn convert(&self) -> Result<(), CliError> {
+        let start = std::time::Instant::now();
+        let (input, output) = (self.io.input.as_path(), self.io.output.as_path());
+        summary::record_input(input);
+
+        if self.key.is_some() && self.to == ArchiveType::Bar {
+            return Err(CliError::InvalidArgument(
+                "--key can only be used when converting to SHARC; BAR archives aren't keyed"
+                    .to_string(),
+            ));
+        }
+
+        let data = common::read_archive(input)?;
+        if magic::sdat_matcher(&data) {
+            return Err(CliError::InvalidArgument(format!(
+                "{} is an SDAT; unwrap it with `sdat extract` and convert the archive inside instead",
+                input.display()
+            )));
+        }
+
+        let archive = decode_archive(&data, input)?;
+        if archive.kind == self.to {
+            return Err(CliError::InvalidArgument(format!(
+                "{} is already a {} archive; use `repack` to re-encode it",
+                input.display(),
+                kind_name(self.to)
+            )));
+        }
+
+        let bytes = match self.to {
+            ArchiveType::Sharc => build_sharc(
+                &archive,
+                keys::resolve_or(self.key.as_ref(), SHARC_DEFAULT_KEY)?,
+            )?,
+            ArchiveType::Bar => build_bar(&archive)?,
+        };
+
+        let mut output_file = common::create_output_file(output)?;
+        output_file
+            .write_all(&bytes)
+            .and_then(|()| output_file.flush())
+            .map_err(|e| CliError::io("failed to write output file", e))?;
+
+        common::print_created(
+            &format!(
+                "Converted {} archive to {}: {}",
+                kind_name(archive.kind),
+                kind_name(self.to),
+                output.display()
+            ),
+            output,
+            start,
+        );
+        Ok(())
+    }
+}
+
+/// Human-readable name of an archive format, for messages.
+const fn kind_name(kind: ArchiveType) -> &'static str {
+    match kind {
+        ArchiveType::Sharc => "SHARC",
+        ArchiveType::Bar => "BAR",
+    }
+}
+
+/// Reads a SHARC or BAR archive and decodes every entry.
+fn decode_archive(data: &[u8], input: &std::path::Path) -> Result<DecodedArchive, CliError> {
+    match magic::extract_version(data) {
+        Some(ArchiveVersion::SHARC) => {
+            let (sharc, endian) = common::read_sharc(data, SHARC_DEFAULT_KEY)?;
+            let entries = decode_entries(&sharc.entries, |entry| {
+                sharc.entry_data(&mut std::io::Cursor::new(data), entry)
+            })?;
+
+            Ok(DecodedArchive {
+                kind: ArchiveType::Sharc,
+                timestamp: sharc.archive_data.timestamp,
+                endian,
+                entries,
+            })
+        }
+        Some(ArchiveVersion::BAR) => {
+            let (bar, endian) = common::read_bar(data)?;
+            let entries = decode_entries(&bar.entries, |entry| {
+                bar.entry_data(
+                    &mut std::io::Cursor::new(data),
+                    entry,
+                    &BAR_DEFAULT_KEY,
+                    &BAR_SIGNATURE_KEY,
+                )
+            })?;
+
+            Ok(DecodedArchive {
+                kind: ArchiveType::Bar,
+                timestamp: bar.archive_data.timestamp,
+                endian,
+                entries,
+            })
+        }
+        _ => Err(CliError::Archive(format!(
+            "{} is not a SHARC or BAR archive",
+            input.display()
+        ))),
+    }
+}
+
+/// Decodes `entries` with `decode`, keeping each entry's hash and compression.
+fn decode_entries<E: std::fmt::Display>(
+    entries: &[Entry],
+    decode: impl Fn(&Entry) -> Result<Vec<u8>, E> + Sync,
+) -> Result<Vec<DecodedEntry>, CliError> {
+    let progress = common::progress_bar(entries.len());
+
+    let decode_entry = |entry: &Entry| {
+        let data = decode(entry).map_err(|e| {
+            CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+        })?;
+
+        progress.inc(1);
+        Ok::<_, CliError>(DecodedEntry {
+            name_hash: entry.name_hash,
+            compression: entry.location.1,
+            data,
+        })
+    };
+
+    #[cfg(not(feature = "rayon"))]
+    let decoded: Result<Vec<_>, CliError> = entries.iter().map(decode_entry).collect();
+
+    #[cfg(feature = "rayon")]
+    let decoded: Result<Vec<_>, CliError> = entries.par_iter().map(decode_entry).collect();
+
+    progress.finish_and_clear();
+    decoded
+}
+
+fn build_sharc(archive: &DecodedArchive, key: [u8; 32]) -> Result<Vec<u8>, CliError> {
+    let mut archive_writer =
+        SharcBuilder::new(key, SHARC_FILES_KEY).with_timestamp(archive.timestamp);
+
+    // SHARC entries are compressed and encrypted individually, so that's done in
+    // parallel and only adding them has to happen in order
+    let compress_entry = |entry: &DecodedEntry| {
+        common::compress_sharc_entry(
+            &archive_writer,
+            entry.name_hash,
+            entry.name_hash.to_string().into(),
+            &entry.data,
+            entry.compression,
+            common::entry_iv(entry.name_hash, false),
+            false,
+        )
+    };
+
+    #[cfg(not(feature = "rayon"))]
+    let compressed_data: Result<Vec<_>, CliError> =
+        archive.entries.iter().map(compress_entry).collect();
+
+    #[cfg(feature = "rayon")]
+    let compressed_data: Result<Vec<_>, CliError> =
+        archive.entries.par_iter().map(compress_entry).collect();
+
+    for file in compressed_data? {
+        summary::record_entries(1, file.uncompressed_size as u64);
+        archive_writer.add_compressed_entry(
+            file.name_hash,
+            file.compressed_data,
+            file.uncompressed_size as u32,
+            file.compression,
+            file.iv,
+        );
+    }
+
+    let mut buf = Vec::new();
+    archive_writer
+        .build(&mut std::io::Cursor::new(&mut buf), archive.endian)
+        .map_err(|e| format!("failed to finalize SHARC: {e}"))?;
+    Ok(buf)
+}
+
+fn build_bar(archive: &DecodedArchive) -> Result<Vec<u8>, CliError> {
+    let mut archive_writer = BarBuilder::new(BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY)
+        .with_flags(ArchiveFlags(ArchiveFlagsValue::Protected.into()))
+        .with_timestamp(archive.timestamp);
+
+    for entry in &archive.entries {
+        summary::record_entries(1, entry.data.len() as u64);
+        archive_writer.add_entry(
+            entry.name_hash,
+            SmallVec::from_slice(&entry.data),
+            entry.compression,
+        );
+    }
+
+    let mut buf = Vec::new();
+    archive_writer
+        .build(&mut std::io::Cursor::new(&mut buf), archive.endian)
+        .map_err(|e| format!("failed to finalize archive: {e}"))?;
+    Ok(buf)
+}
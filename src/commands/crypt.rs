@@ -1,7 +1,10 @@
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 use crate::{
-    commands::{Execute, IOArgs},
+    commands::{Execute, IOArgs, common, summary},
+    error::CliError,
+    keys::{self, KeyArg},
     magic::MimeType,
 };
 use clap::{Args, Subcommand, ValueEnum};
@@ -11,11 +14,25 @@ use hdk_secure::{
     reader::CryptoReader,
 };
 
+#[derive(Args, Debug)]
+pub struct EncryptArgs {
+    #[clap(flatten)]
+    pub io: IOArgs,
+
+    /// Blowfish key, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
 #[derive(Args, Debug)]
 pub struct DecryptArgs {
     #[clap(flatten)]
     pub io: IOArgs,
 
+    /// Blowfish key, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+
     /// Hint the expected plaintext file type for the known-plaintext IV recovery.
     ///
     /// If omitted, all known types are tried automatically.
@@ -93,7 +110,7 @@ impl KnownFileType {
 pub enum Crypt {
     /// Encrypt a file
     #[clap(alias = "e")]
-    Encrypt(IOArgs),
+    Encrypt(EncryptArgs),
     /// Decrypt a file using known-plaintext IV recovery
     #[clap(alias = "d")]
     Decrypt(DecryptArgs),
@@ -105,17 +122,20 @@ pub enum Crypt {
 }
 
 impl Execute for Crypt {
-    fn execute(self) {
-        let result = match self {
-            Self::Encrypt(ref args) => encrypt_file(&args.input, &args.output),
-            Self::Decrypt(ref args) => {
-                decrypt_file(&args.io.input, &args.io.output, args.file_type)
-            }
+    fn execute(self) -> Result<(), CliError> {
+        match self {
+            Self::Encrypt(ref args) => encrypt_file(
+                &args.io.input,
+                &args.io.output,
+                &keys::resolve_or(args.key.as_ref(), keys::BLOWFISH_DEFAULT_KEY)?,
+            ),
+            Self::Decrypt(ref args) => decrypt_file(
+                &args.io.input,
+                &args.io.output,
+                &keys::resolve_or(args.key.as_ref(), keys::BLOWFISH_DEFAULT_KEY)?,
+                args.file_type,
+            ),
             Self::Auto(ref args) => auto_crypt(&args.input, args.file_type),
-        };
-
-        if let Err(e) = result {
-            eprintln!("Error: {e}");
         }
     }
 }
@@ -178,9 +198,11 @@ fn recover_iv(
     key: &[u8; 32],
     ciphertext: &[u8],
     known_plaintext: &[u8; 8],
-) -> Result<[u8; 8], String> {
+) -> Result<[u8; 8], CliError> {
     if ciphertext.len() < 8 {
-        return Err("Ciphertext too short for IV recovery".to_string());
+        return Err(CliError::Decode(
+            "Ciphertext too short for IV recovery".to_string(),
+        ));
     }
 
     // Step 1: XOR known plaintext against the first ciphertext block → ECB(IV)
@@ -194,12 +216,12 @@ fn recover_iv(
     // `BlowfishEcbDec` is `ecb::Decryptor<Blowfish>` and operates on 8-byte blocks.
     use ctr::cipher::{BlockDecryptMut, KeyInit, block_padding::NoPadding};
     let ecb_cipher = BlowfishEcbDec::new_from_slice(key)
-        .map_err(|e| format!("Failed to create ECB cipher: {e}"))?;
+        .map_err(|e| CliError::Decode(format!("Failed to create ECB cipher: {e}")))?;
 
     let mut block = ecb_iv;
     ecb_cipher
         .decrypt_padded_mut::<NoPadding>(&mut block)
-        .map_err(|e| format!("ECB decrypt failed: {e}"))?;
+        .map_err(|e| CliError::Decode(format!("ECB decrypt failed: {e}")))?;
 
     Ok(block)
 }
@@ -223,9 +245,11 @@ const HCDB_MAX_SEGMENTS: u16 = u16::MAX;
 ///
 /// Verification oracle: decrypt the first 16 bytes with each candidate IV and check
 /// that the `u32` at plaintext bytes 12-15 (big-endian) equals the total file size.
-fn brute_force_hcdb_iv(key: &[u8; 32], ciphertext: &[u8]) -> Result<(u16, [u8; 8]), String> {
+fn brute_force_hcdb_iv(key: &[u8; 32], ciphertext: &[u8]) -> Result<(u16, [u8; 8]), CliError> {
     if ciphertext.len() < 16 {
-        return Err("HCDB ciphertext too short (need at least 16 bytes)".to_string());
+        return Err(CliError::Decode(
+            "HCDB ciphertext too short (need at least 16 bytes)".to_string(),
+        ));
     }
 
     let file_size = ciphertext.len() as u32;
@@ -249,11 +273,11 @@ fn brute_force_hcdb_iv(key: &[u8; 32], ciphertext: &[u8]) -> Result<(u16, [u8; 8
 
         // Step 2: ECB-decrypt to recover the raw IV.
         let ecb_dec = BlowfishEcbDec::new_from_slice(key)
-            .map_err(|e| format!("ECB cipher init failed: {e}"))?;
+            .map_err(|e| CliError::Decode(format!("ECB cipher init failed: {e}")))?;
         let mut iv_candidate = ecb_iv;
         ecb_dec
             .decrypt_padded_mut::<NoPadding>(&mut iv_candidate)
-            .map_err(|e| format!("ECB decrypt failed: {e}"))?;
+            .map_err(|e| CliError::Decode(format!("ECB decrypt failed: {e}")))?;
 
         // Step 3: CTR-decrypt the first 16 bytes using the candidate IV.
         //
@@ -261,12 +285,12 @@ fn brute_force_hcdb_iv(key: &[u8; 32], ciphertext: &[u8]) -> Result<(u16, [u8; 8
         // We already have ECB_k(IV) = ecb_iv (before the ECB-decrypt step above).
         // Compute ECB_k(IV+1) on the fly.
         let iv_plus_one = (u64::from_be_bytes(iv_candidate).wrapping_add(1)).to_be_bytes();
-        let ecb_enc =
-            BlowfishEcb::new_from_slice(key).map_err(|e| format!("ECB enc init failed: {e}"))?;
+        let ecb_enc = BlowfishEcb::new_from_slice(key)
+            .map_err(|e| CliError::Decode(format!("ECB enc init failed: {e}")))?;
         let mut keystream_block1 = iv_plus_one;
         ecb_enc
             .encrypt_padded_mut::<NoPadding>(&mut keystream_block1, 8)
-            .map_err(|e| format!("ECB encrypt failed: {e}"))?;
+            .map_err(|e| CliError::Decode(format!("ECB encrypt failed: {e}")))?;
 
         let mut plain16 = [0u8; 16];
         for i in 0..8 {
@@ -277,19 +301,21 @@ fn brute_force_hcdb_iv(key: &[u8; 32], ciphertext: &[u8]) -> Result<(u16, [u8; 8
         // Step 4: Oracle — bytes 12-15 of HCDB plaintext are the file size (BE u32).
         let size_field = u32::from_be_bytes(plain16[12..16].try_into().unwrap());
         if size_field == file_size {
-            eprintln!(
+            common::log_entry(&format!(
                 "  [Hcdb] found segment count = {seg_count}, IV = {:02x?}",
                 iv_candidate
-            );
+            ));
             return Ok((seg_count, iv_candidate));
         }
     }
 
-    Err("HCDB brute-force exhausted all segment counts without a match".to_string())
+    Err(CliError::Decode(
+        "HCDB brute-force exhausted all segment counts without a match".to_string(),
+    ))
 }
 
 /// CTR-decrypt `data` in-place using the given key and IV.
-fn ctr_decrypt_inplace(key: &[u8; 32], iv: &[u8; 8], data: &mut Vec<u8>) -> Result<(), String> {
+fn ctr_decrypt_inplace(key: &[u8; 32], iv: &[u8; 8], data: &mut Vec<u8>) -> Result<(), CliError> {
     use std::io::Read;
 
     let cipher = BlowfishPS3::new(key.into(), iv.into());
@@ -299,12 +325,23 @@ fn ctr_decrypt_inplace(key: &[u8; 32], iv: &[u8; 8], data: &mut Vec<u8>) -> Resu
     let mut decrypted = Vec::with_capacity(data.len());
     reader
         .read_to_end(&mut decrypted)
-        .map_err(|e| format!("CTR decrypt failed: {e}"))?;
+        .map_err(|e| CliError::Decode(format!("CTR decrypt failed: {e}")))?;
 
     *data = decrypted;
     Ok(())
 }
 
+/// Writes `data` to `output`, or to stdout if it's `-`.
+fn write_output(output: &Path, data: &[u8]) -> std::io::Result<()> {
+    if !common::is_stdio(output) {
+        return std::fs::write(output, data);
+    }
+
+    let mut stdout = common::open_output(output).map_err(std::io::Error::other)?;
+    stdout.write_all(data)?;
+    stdout.flush()
+}
+
 // ---------------------------------------------------------------------------
 // Public commands
 // ---------------------------------------------------------------------------
@@ -312,11 +349,11 @@ fn ctr_decrypt_inplace(key: &[u8; 32], iv: &[u8; 8], data: &mut Vec<u8>) -> Resu
 /// Encrypt `input` → `output`.
 ///
 /// The IV is derived from the SHA-1 hash of the plaintext (first 8 bytes of the digest).
-pub fn encrypt_file(input: &PathBuf, output: &PathBuf) -> Result<(), String> {
+pub fn encrypt_file(input: &Path, output: &PathBuf, key: &[u8; 32]) -> Result<(), CliError> {
     use std::io::Read;
 
-    let data =
-        std::fs::read(input).map_err(|e| format!("Failed to read file for encryption: {e}"))?;
+    summary::record_input(input);
+    let data = common::read_input(input)?;
 
     // Derive IV from SHA-1 of the plaintext.
     let mut hasher = sha1_smol::Sha1::new();
@@ -324,21 +361,30 @@ pub fn encrypt_file(input: &PathBuf, output: &PathBuf) -> Result<(), String> {
     let digest = hasher.digest().bytes();
 
     let iv: [u8; 8] = digest[..8].try_into().unwrap();
-    println!("IV (from SHA-1): {:02x?}", iv);
+    common::log_info(&format!("IV (from SHA-1): {:02x?}", iv));
 
-    let cipher = BlowfishPS3::new(&crate::keys::BLOWFISH_DEFAULT_KEY.into(), &iv.into());
+    let cipher = BlowfishPS3::new(key.into(), &iv.into());
     let mut cursor = std::io::Cursor::new(data.as_slice());
     let mut reader = CryptoReader::new(&mut cursor, cipher);
 
     let mut encrypted = Vec::with_capacity(data.len());
     reader
         .read_to_end(&mut encrypted)
-        .map_err(|e| format!("Encryption failed: {e}"))?;
-
-    std::fs::write(output, &encrypted)
-        .map_err(|e| format!("Failed to write encrypted file: {e}"))?;
-
-    println!("Encrypted → {}", output.display());
+        .map_err(|e| CliError::Decode(format!("Encryption failed: {e}")))?;
+
+    write_output(output, &encrypted)
+        .map_err(|e| CliError::io("Failed to write encrypted file", e))?;
+    summary::record_output(output);
+    summary::record_entries(1, encrypted.len() as u64);
+
+    common::print_result(
+        &format!(
+            "Encrypted → {} ({} bytes)",
+            output.display(),
+            encrypted.len()
+        ),
+        &serde_json::json!({ "output": output, "bytes": encrypted.len() }),
+    );
     Ok(())
 }
 
@@ -348,14 +394,13 @@ pub fn encrypt_file(input: &PathBuf, output: &PathBuf) -> Result<(), String> {
 /// Otherwise every [`KnownFileType`] is attempted and the first that produces
 /// recognizable output is used.
 pub fn decrypt_file(
-    input: &PathBuf,
+    input: &Path,
     output: &PathBuf,
+    key: &[u8; 32],
     hint: Option<KnownFileType>,
-) -> Result<(), String> {
-    let data =
-        std::fs::read(input).map_err(|e| format!("Failed to read file for decryption: {e}"))?;
-
-    let key = &crate::keys::BLOWFISH_DEFAULT_KEY;
+) -> Result<(), CliError> {
+    summary::record_input(input);
+    let data = common::read_input(input)?;
 
     let candidates: &[KnownFileType] = hint
         .as_ref()
@@ -366,13 +411,13 @@ pub fn decrypt_file(
         // HCDB has an unknown 2-byte segment count in its header, so we brute-force
         // all 65536 values and use a size-field oracle rather than the generic KPA path.
         let (iv, verified_by_oracle) = if *file_type == KnownFileType::Hcdb {
-            eprintln!("  [Hcdb] brute-forcing segment count (0..=65535)…");
+            common::log_entry("  [Hcdb] brute-forcing segment count (0..=65535)…");
             match brute_force_hcdb_iv(key, &data) {
                 // The brute-force already confirmed correctness via the file-size oracle,
                 // so we can skip the entropy check for this type.
                 Ok((_seg_count, iv)) => (iv, true),
                 Err(e) => {
-                    eprintln!("  [Hcdb] brute-force failed: {e}");
+                    common::log_entry(&format!("  [Hcdb] brute-force failed: {e}"));
                     continue;
                 }
             }
@@ -384,7 +429,7 @@ pub fn decrypt_file(
             match recover_iv(key, &data, &known) {
                 Ok(iv) => (iv, false),
                 Err(e) => {
-                    eprintln!("  [{file_type:?}] IV recovery failed: {e}");
+                    common::log_entry(&format!("  [{file_type:?}] IV recovery failed: {e}"));
                     continue;
                 }
             }
@@ -392,7 +437,7 @@ pub fn decrypt_file(
 
         let mut attempt = data.clone();
         if let Err(e) = ctr_decrypt_inplace(key, &iv, &mut attempt) {
-            eprintln!("  [{file_type:?}] CTR decrypt failed: {e}");
+            common::log_entry(&format!("  [{file_type:?}] CTR decrypt failed: {e}"));
             continue;
         }
 
@@ -400,10 +445,10 @@ pub fn decrypt_file(
         // the file-size field), so skip entropy checking — HCDB bodies are EdgeLZMA-
         // compressed and will still read as high-entropy after decryption.
         let success = if verified_by_oracle {
-            println!(
+            common::log_info(&format!(
                 "Decrypted as {file_type:?} (validated by file-size oracle), IV: {:02x?}",
                 iv
-            );
+            ));
             true
         } else {
             // Verification: we CANNOT use magic bytes here because the KPA forces
@@ -418,15 +463,15 @@ pub fn decrypt_file(
             let entropy_after = entropy::shannon_entropy(&attempt[body_start..]);
             let drop = entropy_before - entropy_after;
 
-            eprintln!(
+            common::log_entry(&format!(
                 "  [{file_type:?}] entropy {entropy_before:.3} → {entropy_after:.3} (drop {drop:.3})"
-            );
+            ));
 
             if drop >= ENTROPY_DROP_THRESHOLD {
-                println!(
+                common::log_info(&format!(
                     "Decrypted as {file_type:?} (entropy drop {drop:.3}), IV: {:02x?}",
                     iv
-                );
+                ));
                 true
             } else {
                 false
@@ -434,28 +479,39 @@ pub fn decrypt_file(
         };
 
         if success {
-            std::fs::write(output, &attempt)
-                .map_err(|e| format!("Failed to write decrypted file: {e}"))?;
-            println!("Decrypted → {}", output.display());
+            write_output(output, &attempt)
+                .map_err(|e| CliError::io("Failed to write decrypted file", e))?;
+            summary::record_output(output);
+            summary::record_entries(1, attempt.len() as u64);
+            common::print_result(
+                &format!("Decrypted → {} ({} bytes)", output.display(), attempt.len()),
+                &serde_json::json!({
+                    "output": output,
+                    "bytes": attempt.len(),
+                    "file_type": format!("{file_type:?}"),
+                }),
+            );
             return Ok(());
         }
         // Not a match — try the next candidate.
     }
 
-    Err(format!(
+    Err(CliError::Decode(format!(
         "Could not decrypt '{}': none of the known-plaintext candidates produced recognizable output.\n\
          Try specifying --type explicitly.",
         input.display()
-    ))
+    )))
 }
 
 /// Auto mode: detect whether the file is encrypted or decrypted, then do the reverse.
-pub fn auto_crypt(input: &PathBuf, hint: Option<KnownFileType>) -> Result<(), String> {
-    let data = std::fs::read(input).map_err(|e| format!("Failed to read file: {e}"))?;
+pub fn auto_crypt(input: &PathBuf, hint: Option<KnownFileType>) -> Result<(), CliError> {
+    let data = std::fs::read(input).map_err(|e| CliError::io("Failed to read file", e))?;
 
     match status_heuristic(&data) {
         Heuristic::Decrypted(reason) => {
-            println!("File appears decrypted ({reason:?}) — encrypting…");
+            common::log_info(&format!(
+                "File appears decrypted ({reason:?}) — encrypting…"
+            ));
             // Place output next to input with a `.enc` extension.
             let output = input.with_extension(
                 format!(
@@ -464,10 +520,12 @@ pub fn auto_crypt(input: &PathBuf, hint: Option<KnownFileType>) -> Result<(), St
                 )
                 .trim_start_matches('.'),
             );
-            encrypt_file(input, &output)
+            encrypt_file(input, &output, &crate::keys::BLOWFISH_DEFAULT_KEY)
         }
         Heuristic::Encrypted(reason) => {
-            println!("File appears encrypted ({reason:?}) — decrypting…");
+            common::log_info(&format!(
+                "File appears encrypted ({reason:?}) — decrypting…"
+            ));
             // Place output next to input with a `.dec` extension.
             let output = input.with_extension(
                 format!(
@@ -476,7 +534,7 @@ pub fn auto_crypt(input: &PathBuf, hint: Option<KnownFileType>) -> Result<(), St
                 )
                 .trim_start_matches('.'),
             );
-            decrypt_file(input, &output, hint)
+            decrypt_file(input, &output, &crate::keys::BLOWFISH_DEFAULT_KEY, hint)
         }
     }
 }
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use clap::Args;
+use hdk_archive::structs::Entry;
+use hdk_secure::hash::AfsHash;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::commands::{Execute, common, summary};
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub struct Diff {
+    /// The two SHARC / BAR archives to compare, old then new, optionally wrapped in SDAT
+    #[clap(short, long = "input", num_args = 2, value_names = ["OLD", "NEW"], required = true)]
+    pub inputs: Vec<PathBuf>,
+}
+
+impl Execute for Diff {
+    fn execute(self) -> Result<(), CliError> {
+        self.diff()
+    }
+}
+
+impl Diff {
+    fn diff(&self) -> Result<(), CliError> {
+        let [old_path, new_path] = self.inputs.as_slice() else {
+            return Err(CliError::InvalidArgument(
+                "expected an old and a new archive".to_string(),
+            ));
+        };
+        summary::record_input(old_path);
+        summary::record_input(new_path);
+
+        let old = common::OpenArchive::open(old_path)?;
+        let new = common::OpenArchive::open(new_path)?;
+
+        let old_entries: HashMap<i32, &Entry> =
+            old.entries().iter().map(|e| (e.name_hash.0, e)).collect();
+        let new_entries: HashMap<i32, &Entry> =
+            new.entries().iter().map(|e| (e.name_hash.0, e)).collect();
+
+        let mut added: Vec<AfsHash> = new_entries
+            .values()
+            .filter(|e| !old_entries.contains_key(&e.name_hash.0))
+            .map(|e| e.name_hash)
+            .collect();
+        let mut removed: Vec<AfsHash> = old_entries
+            .values()
+            .filter(|e| !new_entries.contains_key(&e.name_hash.0))
+            .map(|e| e.name_hash)
+            .collect();
+        let common_entries: Vec<(&Entry, &Entry)> = old_entries
+            .values()
+            .filter_map(|old_entry| {
+                new_entries
+                    .get(&old_entry.name_hash.0)
+                    .map(|new_entry| (*old_entry, *new_entry))
+            })
+            .collect();
+
+        let progress = common::progress_bar(common_entries.len());
+
+        // Entries present in both are compared by their decoded contents, so a change in
+        // compression or IV alone doesn't count as a difference
+        let compare = |(old_entry, new_entry): &(&Entry, &Entry)| {
+            let result = Self::entries_differ(&old, old_entry, &new, new_entry)
+                .map(|differ| differ.then_some(old_entry.name_hash));
+            progress.inc(1);
+            result
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let changed: Result<Vec<_>, CliError> = common_entries.iter().map(compare).collect();
+
+        #[cfg(feature = "rayon")]
+        let changed: Result<Vec<_>, CliError> = common_entries.par_iter().map(compare).collect();
+
+        progress.finish_and_clear();
+
+        let mut changed: Vec<AfsHash> = changed?.into_iter().flatten().collect();
+        let unchanged = common_entries.len() - changed.len();
+        summary::record_entries(old_entries.len() + added.len(), 0);
+
+        for list in [&mut added, &mut removed, &mut changed] {
+            list.sort_by_key(|hash| hash.0);
+        }
+
+        for (kind, hashes) in [
+            ("added", &added),
+            ("removed", &removed),
+            ("changed", &changed),
+        ] {
+            for hash in hashes {
+                common::log_entry(&format!("{kind} {hash}"));
+            }
+        }
+
+        let to_strings = |hashes: &[AfsHash]| -> Vec<String> {
+            hashes.iter().map(ToString::to_string).collect()
+        };
+        common::print_result(
+            &format!(
+                "{} added, {} removed, {} changed, {unchanged} unchanged",
+                added.len(),
+                removed.len(),
+                changed.len()
+            ),
+            &serde_json::json!({
+                "added": to_strings(&added),
+                "removed": to_strings(&removed),
+                "changed": to_strings(&changed),
+                "unchanged": unchanged,
+            }),
+        );
+
+        let differences = added.len() + removed.len() + changed.len();
+        if differences > 0 {
+            return Err(CliError::Other(format!(
+                "the archives differ in {differences} entries"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether two entries with the same name hash decode to different contents.
+    fn entries_differ(
+        old: &common::OpenArchive,
+        old_entry: &Entry,
+        new: &common::OpenArchive,
+        new_entry: &Entry,
+    ) -> Result<bool, CliError> {
+        if old_entry.uncompressed_size != new_entry.uncompressed_size {
+            return Ok(true);
+        }
+
+        let old_data = common::timed(
+            || format!("Decoding old {}", old_entry.name_hash),
+            || old.entry_data(old_entry),
+        )?;
+        let new_data = common::timed(
+            || format!("Decoding new {}", new_entry.name_hash),
+            || new.entry_data(new_entry),
+        )?;
+
+        Ok(old_data != new_data)
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::Args;
+use hdk_secure::hash::AfsHash;
+
+use crate::commands::{Execute, common, summary};
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub struct ExtractOne {
+    /// Input SHARC / BAR archive, optionally wrapped in SDAT, or `-` for stdin
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// Name hash of the entry to extract, as used for extracted file names
+    #[clap(long, value_parser = common::parse_hash)]
+    pub hash: AfsHash,
+
+    /// File to write the entry to, or `-` for stdout
+    #[clap(short, long)]
+    pub output: PathBuf,
+}
+
+impl Execute for ExtractOne {
+    fn execute(self) -> Result<(), CliError> {
+        self.extract()
+    }
+}
+
+impl ExtractOne {
+    fn extract(&self) -> Result<(), CliError> {
+        summary::record_input(&self.input);
+
+        let archive = common::OpenArchive::open(&self.input)?;
+        let entry = archive
+            .entries()
+            .iter()
+            .find(|entry| entry.name_hash.0 == self.hash.0)
+            .ok_or_else(|| format!("no entry with hash {} in the archive", self.hash))?;
+
+        let data = archive.entry_data(entry)?;
+        summary::record_entries(1, data.len() as u64);
+
+        if self.output.as_os_str() == "-" {
+            let mut stdout = std::io::stdout().lock();
+            return stdout
+                .write_all(&data)
+                .and_then(|()| stdout.flush())
+                .map_err(|e| CliError::io("failed to write to stdout", e));
+        }
+
+        common::create_output_file(&self.output)?
+            .write_all(&data)
+            .map_err(|e| CliError::io(format!("failed to write {}", self.output.display()), e))?;
+
+        common::print_result(
+            &format!(
+                "Extracted {} ({} bytes) to {}",
+                self.hash,
+                data.len(),
+                self.output.display()
+            ),
+            &serde_json::json!({
+                "hash": self.hash.to_string(),
+                "output": self.output,
+                "bytes": data.len(),
+            }),
+        );
+        Ok(())
+    }
+}
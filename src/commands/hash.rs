@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::commands::{Execute, common, summary};
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub struct Hash {
+    /// Archive path to hash (repeatable); read one per line from stdin if not given
+    #[clap(short, long = "path")]
+    pub paths: Vec<String>,
+
+    /// Only print the paths whose hash is present in this SHARC / BAR / SDAT archive
+    #[clap(long, value_name = "ARCHIVE")]
+    pub verify: Option<PathBuf>,
+}
+
+impl Execute for Hash {
+    fn execute(self) -> Result<(), CliError> {
+        self.hash()
+    }
+}
+
+impl Hash {
+    fn hash(&self) -> Result<(), CliError> {
+        let mut paths = self.paths.clone();
+        if paths.is_empty() {
+            for line in std::io::stdin().lock().lines() {
+                let line = line.map_err(|e| CliError::io("failed to read stdin", e))?;
+                if !line.trim().is_empty() {
+                    paths.push(line.trim().to_string());
+                }
+            }
+        }
+
+        let present = match &self.verify {
+            Some(archive) => {
+                summary::record_input(archive);
+                let entries = common::read_archive_entries(archive)?;
+                Some(
+                    entries
+                        .iter()
+                        .map(|e| e.name_hash.0)
+                        .collect::<HashSet<_>>(),
+                )
+            }
+            None => None,
+        };
+
+        let hashed: Vec<_> = paths
+            .iter()
+            .map(|path| (path, common::hash_path(path)))
+            .collect();
+        summary::record_entries(hashed.len(), 0);
+
+        let Some(present) = present else {
+            if crate::commands::globals().json {
+                let hashes: Vec<_> = hashed
+                    .iter()
+                    .map(|(path, hash)| serde_json::json!({ "path": path, "hash": hash.to_string() }))
+                    .collect();
+                println!("{}", serde_json::Value::from(hashes));
+            } else {
+                for (path, hash) in &hashed {
+                    println!("{hash}  {path}");
+                }
+            }
+            return Ok(());
+        };
+
+        // Found paths go to stdout on their own, so the output is a ready-made `--names` list
+        let found: Vec<_> = hashed
+            .iter()
+            .filter(|(_, hash)| present.contains(&hash.0))
+            .collect();
+
+        if crate::commands::globals().json {
+            let found: Vec<_> = found
+                .iter()
+                .map(|(path, hash)| serde_json::json!({ "path": path, "hash": hash.to_string() }))
+                .collect();
+            println!("{}", serde_json::Value::from(found));
+        } else {
+            for (path, _) in &found {
+                println!("{path}");
+            }
+        }
+
+        if !crate::commands::globals().quiet {
+            eprintln!(
+                "Found {} of {} paths in the archive",
+                found.len(),
+                hashed.len()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::{
+    commands::{Execute, common, summary},
+    error::CliError,
+    magic,
+};
+
+/// Name reported for files that no matcher recognises.
+const UNKNOWN_TYPE: &str = "unknown";
+
+#[derive(Args, Debug)]
+pub struct Identify {
+    /// Input file / folder path, or `-` for stdin
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// Walk `--input` as a folder and identify every file in it
+    #[clap(short, long, default_value_t = false)]
+    pub recursive: bool,
+
+    /// Print the number of files of each type instead of one line per file
+    #[clap(long, default_value_t = false, requires = "recursive")]
+    pub summary: bool,
+}
+
+impl Execute for Identify {
+    fn execute(self) -> Result<(), CliError> {
+        self.identify()
+    }
+}
+
+impl Identify {
+    fn identify(&self) -> Result<(), CliError> {
+        summary::record_input(&self.input);
+
+        if common::is_stdio(&self.input) {
+            if self.recursive {
+                return Err(CliError::InvalidArgument(
+                    "--recursive needs a folder, not `--input -`".to_string(),
+                ));
+            }
+
+            let results = [(
+                self.input.clone(),
+                Self::identify_data(&magic::get_matcher(), &common::read_input(&self.input)?),
+            )];
+            summary::record_entries(1, 0);
+            Self::print_files(&results, crate::commands::globals().json);
+            return Ok(());
+        }
+
+        let paths = if self.recursive {
+            if !self.input.is_dir() {
+                return Err(CliError::InvalidArgument(format!(
+                    "{} is not a folder",
+                    self.input.display()
+                )));
+            }
+
+            walkdir::WalkDir::new(&self.input)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .map(walkdir::DirEntry::into_path)
+                .collect()
+        } else {
+            vec![self.input.clone()]
+        };
+
+        let matcher = magic::get_matcher();
+        let mut results = Vec::with_capacity(paths.len());
+        for path in paths {
+            // Only the header and tail are needed, so multi-GB files stay cheap to scan
+            let data = magic::sample_file(&path)
+                .map_err(|e| CliError::io(format!("failed to read file {}", path.display()), e))?;
+
+            results.push((path, Self::identify_data(&matcher, &data)));
+        }
+
+        summary::record_entries(results.len(), 0);
+
+        if self.summary {
+            Self::print_summary(&results, crate::commands::globals().json);
+        } else {
+            Self::print_files(&results, crate::commands::globals().json);
+        }
+
+        Ok(())
+    }
+
+    /// The MIME type of `data`, or [`UNKNOWN_TYPE`].
+    fn identify_data(matcher: &infer::Infer, data: &[u8]) -> &'static str {
+        matcher
+            .get(data)
+            .map_or(UNKNOWN_TYPE, |kind| kind.mime_type())
+    }
+
+    fn print_files(results: &[(PathBuf, &str)], json: bool) {
+        if json {
+            let entries: Vec<_> = results
+                .iter()
+                .map(|(path, kind)| serde_json::json!({ "path": path, "type": kind }))
+                .collect();
+            println!("{}", serde_json::Value::Array(entries));
+            return;
+        }
+
+        for (path, kind) in results {
+            println!("{}: {kind}", path.display());
+        }
+    }
+
+    fn print_summary(results: &[(PathBuf, &str)], json: bool) {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for (_, kind) in results {
+            *counts.entry(kind).or_default() += 1;
+        }
+
+        if json {
+            println!("{}", serde_json::json!(counts));
+            return;
+        }
+
+        for (kind, count) in &counts {
+            println!("{kind}: {count}");
+        }
+        println!("{} files", results.len());
+    }
+}
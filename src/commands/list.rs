@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::commands::{Execute, common, summary};
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub struct List {
+    /// Input SHARC / BAR archive, optionally wrapped in SDAT, or `-` for stdin
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// Print a final line with the entry count and total sizes
+    #[clap(long, default_value_t = false)]
+    pub total: bool,
+}
+
+impl Execute for List {
+    fn execute(self) -> Result<(), CliError> {
+        self.list()
+    }
+}
+
+impl List {
+    fn list(&self) -> Result<(), CliError> {
+        summary::record_input(&self.input);
+
+        let entries = common::read_archive_entries(&self.input)?;
+
+        let compressed: u64 = entries.iter().map(|e| u64::from(e.compressed_size)).sum();
+        let uncompressed: u64 = entries.iter().map(|e| u64::from(e.uncompressed_size)).sum();
+        summary::record_entries(entries.len(), uncompressed);
+
+        if crate::commands::globals().json {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|entry| {
+                    serde_json::json!({
+                        "hash": entry.name_hash.to_string(),
+                        "compressed_size": entry.compressed_size,
+                        "uncompressed_size": entry.uncompressed_size,
+                        "compression": common::compression_name(entry.location.1),
+                    })
+                })
+                .collect();
+
+            println!(
+                "{}",
+                serde_json::json!({
+                    "entries": entries,
+                    "compressed_size": compressed,
+                    "uncompressed_size": uncompressed,
+                })
+            );
+            return Ok(());
+        }
+
+        for entry in &entries {
+            println!(
+                "{}  {:>10}  {:>10}  {}",
+                entry.name_hash,
+                entry.compressed_size,
+                entry.uncompressed_size,
+                common::compression_name(entry.location.1)
+            );
+        }
+
+        if self.total {
+            println!(
+                "{} entries, {compressed} bytes compressed, {uncompressed} bytes uncompressed",
+                entries.len()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use hdk_archive::structs::CompressionType;
+use hdk_secure::hash::AfsHash;
+use serde::Deserialize;
+
+use crate::commands::{CompressionArg, CreateArgs, common, common::InputSource, summary};
+use crate::error::CliError;
+
+/// On-disk layout of a creation manifest.
+///
+/// ```json
+/// {
+///   "timestamp": 1234567890,
+///   "entries": [
+///     { "source": "files/a.xml", "name": "config/a.xml", "compression": "compressed", "iv": "deterministic" }
+///   ]
+/// }
+/// ```
+///
+/// Manifests that don't start with `{` are read as plain text instead, one
+/// `name = source` line per entry:
+///
+/// ```text
+/// # comments and blank lines are ignored
+/// config/a.xml = files/a.xml
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    timestamp: Option<i32>,
+    entries: Vec<RawEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntry {
+    source: PathBuf,
+    name: String,
+    compression: Option<CompressionArg>,
+    iv: Option<String>,
+}
+
+/// Parses a plain-text manifest of `name = source` lines.
+fn parse_text(data: &str) -> Result<RawManifest, CliError> {
+    let mut entries = Vec::new();
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, source) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `name = source`", number + 1))?;
+        let (name, source) = (name.trim(), source.trim());
+        if name.is_empty() || source.is_empty() {
+            return Err(CliError::InvalidArgument(format!(
+                "line {}: expected `name = source`",
+                number + 1
+            )));
+        }
+
+        entries.push(RawEntry {
+            source: PathBuf::from(source),
+            name: name.to_string(),
+            compression: None,
+            iv: None,
+        });
+    }
+
+    Ok(RawManifest {
+        timestamp: None,
+        entries,
+    })
+}
+
+/// How the IV of a SHARC entry is chosen.
+#[derive(Debug, Clone, Copy)]
+pub enum IvPolicy {
+    Random,
+    Deterministic,
+    Fixed([u8; 8]),
+}
+
+impl IvPolicy {
+    fn parse(value: &str) -> Result<Self, CliError> {
+        match value {
+            "random" => Ok(Self::Random),
+            "deterministic" => Ok(Self::Deterministic),
+            hex_str => {
+                let bytes = hex::decode(hex_str).map_err(|e| {
+                    CliError::InvalidArgument(format!("invalid IV '{hex_str}': {e}"))
+                })?;
+                let iv = <[u8; 8]>::try_from(bytes.as_slice())
+                    .map_err(|_| format!("invalid IV '{hex_str}': expected 16 hex digits"))?;
+                Ok(Self::Fixed(iv))
+            }
+        }
+    }
+}
+
+/// A single archive entry.
+#[derive(Debug)]
+pub struct ManifestEntry {
+    pub source: PathBuf,
+    pub name: PathBuf,
+    pub name_hash: AfsHash,
+    pub compression: Option<CompressionType>,
+    pub iv: Option<IvPolicy>,
+}
+
+/// A manifest describing every entry of an archive to create, in order.
+#[derive(Debug)]
+pub struct Manifest {
+    pub timestamp: Option<i32>,
+    pub entries: Vec<ManifestEntry>,
+    by_hash: HashMap<i32, usize>,
+}
+
+impl Manifest {
+    /// Loads the `--manifest-in` of a `create`, if one was given.
+    ///
+    /// A manifest that asks for random IVs is rejected with `--reproducible`.
+    pub fn from_args(args: &CreateArgs) -> Result<Option<Self>, CliError> {
+        let Some(path) = &args.manifest_in else {
+            return Ok(None);
+        };
+
+        let manifest = Self::load(path)?;
+        if args.reproducibility.reproducible && manifest.has_random_ivs() {
+            return Err(CliError::InvalidArgument(
+                "--reproducible can't be used with a manifest that asks for random IVs".to_string(),
+            ));
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Loads a JSON or plain-text manifest and validates that every source file exists.
+    ///
+    /// Relative source paths are resolved against the manifest's own directory.
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        summary::record_input(path);
+
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| CliError::io(format!("failed to read manifest {}", path.display()), e))?;
+        let raw = if data.trim_start().starts_with('{') {
+            serde_json::from_str(&data).map_err(|e| {
+                CliError::InvalidArgument(format!(
+                    "failed to parse manifest {}: {e}",
+                    path.display()
+                ))
+            })?
+        } else {
+            parse_text(&data).map_err(|e| {
+                CliError::InvalidArgument(format!(
+                    "failed to parse manifest {}: {e}",
+                    path.display()
+                ))
+            })?
+        };
+
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut entries = Vec::with_capacity(raw.entries.len());
+        let mut by_hash = HashMap::with_capacity(raw.entries.len());
+
+        for (index, entry) in raw.entries.into_iter().enumerate() {
+            let source = base.join(&entry.source);
+            if !source.is_file() {
+                return Err(CliError::InvalidArgument(format!(
+                    "manifest entry {index}: source file `{}` does not exist",
+                    source.display()
+                )));
+            }
+
+            let name_hash = common::input_name_hash(&entry.name)
+                .map_err(|e| CliError::InvalidArgument(format!("manifest entry {index}: {e}")))?;
+            let iv = entry
+                .iv
+                .as_deref()
+                .map(IvPolicy::parse)
+                .transpose()
+                .map_err(|e| CliError::InvalidArgument(format!("manifest entry {index}: {e}")))?;
+
+            // Keep the first entry for each hash, like the collision check does
+            by_hash.entry(name_hash.0).or_insert(index);
+            entries.push(ManifestEntry {
+                source,
+                name: PathBuf::from(entry.name),
+                name_hash,
+                compression: entry.compression.map(Into::into),
+                iv,
+            });
+        }
+
+        Ok(Self {
+            timestamp: raw.timestamp,
+            entries,
+            by_hash,
+        })
+    }
+
+    /// Returns the (source, archive name, name hash) of every entry, in manifest order.
+    pub fn files(&self) -> Vec<(InputSource, PathBuf, AfsHash)> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                (
+                    InputSource::File(entry.source.clone()),
+                    entry.name.clone(),
+                    entry.name_hash,
+                )
+            })
+            .collect()
+    }
+
+    fn entry(&self, name_hash: AfsHash) -> Option<&ManifestEntry> {
+        self.by_hash
+            .get(&name_hash.0)
+            .map(|&index| &self.entries[index])
+    }
+
+    /// The compression type of an entry, if the manifest sets one.
+    pub fn compression(&self, name_hash: AfsHash) -> Option<CompressionType> {
+        self.entry(name_hash).and_then(|entry| entry.compression)
+    }
+
+    /// Whether any entry asks for a random IV, which `--reproducible` can't honour.
+    pub fn has_random_ivs(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry.iv, Some(IvPolicy::Random)))
+    }
+
+    /// The IV of an entry, falling back to `--deterministic` if the manifest doesn't set one.
+    pub fn iv(&self, name_hash: AfsHash, deterministic: bool) -> [u8; 8] {
+        match self.entry(name_hash).and_then(|entry| entry.iv) {
+            Some(IvPolicy::Fixed(iv)) => iv,
+            Some(IvPolicy::Deterministic) => common::entry_iv(name_hash, true),
+            Some(IvPolicy::Random) => common::entry_iv(name_hash, false),
+            None => common::entry_iv(name_hash, deterministic),
+        }
+    }
+}
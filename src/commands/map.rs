@@ -1,7 +1,10 @@
 use clap::Args;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use crate::commands::Execute;
+use crate::commands::{Execute, common, summary};
+use crate::error::CliError;
 
 use hdk_archive::mapper::Mapper;
 
@@ -9,7 +12,7 @@ const DEFAULT_OUTPUT_SUFFIX: &str = "mapped";
 
 #[derive(Args, Debug)]
 pub struct Map {
-    /// Input directory to map
+    /// Input directory to map, or a single SHARC / BAR archive (optionally wrapped in SDAT)
     #[clap(short, long)]
     pub input: PathBuf,
 
@@ -40,14 +43,59 @@ pub struct Map {
     /// Do **not** use for scenes.
     #[clap(short, long)]
     pub uuid: Option<String>,
+
+    /// (Optional) Write which input file was mapped to which path, and which weren't, to this file.
+    ///
+    /// The report is JSON, or CSV if the file name ends in `.csv`.
+    #[clap(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Keep the temp folder an `--input` archive is extracted to, instead of removing it
+    #[clap(long, default_value_t = false)]
+    pub keep_temp: bool,
 }
 
 impl Execute for Map {
-    fn execute(self) {
-        let mut mapper = Mapper::new(self.input.clone()).with_full(self.full);
+    fn execute(self) -> Result<(), CliError> {
+        summary::record_input(&self.input);
+
+        if !self.input.is_file() {
+            return self.map(&self.input);
+        }
 
-        if let Some(uuid) = self.uuid {
-            mapper = mapper.with_uuid(uuid);
+        // `Mapper` walks a folder, so a single archive is extracted to one first
+        let temp = common::create_temp_dir("map")?;
+        let result = extract_archive(&self.input, &temp).and_then(|()| self.map(&temp));
+
+        if self.keep_temp {
+            common::log_info(&format!("Kept extracted archive in {}", temp.display()));
+        } else if let Err(e) = std::fs::remove_dir_all(&temp) {
+            common::log_info(&format!(
+                "Warning: failed to remove temp folder {}: {e}",
+                temp.display()
+            ));
+        }
+
+        result
+    }
+}
+
+impl Map {
+    /// Maps the hash-named files in `input`, which is either `--input` or the folder
+    /// an `--input` archive was extracted to.
+    fn map(&self, input: &Path) -> Result<(), CliError> {
+        // `Mapper` only reports counts, so the mapping is worked out from the input
+        // hashes and the paths it wrote
+        let sources = self
+            .report
+            .as_ref()
+            .map(|_| hashed_inputs(input))
+            .transpose()?;
+
+        let mut mapper = Mapper::new(input.to_path_buf()).with_full(self.full);
+
+        if let Some(uuid) = &self.uuid {
+            mapper = mapper.with_uuid(uuid.clone());
         }
 
         let output_dir = self
@@ -57,17 +105,155 @@ impl Execute for Map {
 
         mapper = mapper.with_output_folder(output_dir.clone());
 
-        println!("Mapping files to: {}", output_dir.display());
+        common::log_info(&format!("Mapping files to: {}", output_dir.display()));
+        summary::record_output(&output_dir);
 
         let result = mapper.run();
 
-        println!("Mapped {} files.", result.mapped);
+        common::print_result(
+            &format!("Mapped {} files.", result.mapped),
+            &serde_json::json!({
+                "output": output_dir,
+                "mapped": result.mapped,
+                "not_found": result.not_found,
+            }),
+        );
+        summary::record_entries(result.mapped, 0);
 
-        if !result.not_found.is_empty() {
+        if !result.not_found.is_empty() && !crate::commands::globals().quiet {
             println!("{} files could not be mapped:", result.not_found.len());
-            for file in result.not_found {
+            for file in &result.not_found {
                 println!(" - {}", file.display());
             }
         }
+
+        if let (Some(report), Some(sources)) = (&self.report, sources) {
+            write_report(
+                report,
+                &output_dir,
+                &sources,
+                result.mapped,
+                &result.not_found,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Extracts every entry of the archive at `input` into `dir`, named by its hash.
+fn extract_archive(input: &Path, dir: &Path) -> Result<(), CliError> {
+    let archive = common::OpenArchive::open(input)?;
+    let entries = archive.entries();
+    common::log_info(&format!(
+        "Extracting {} entries to {}",
+        entries.len(),
+        dir.display()
+    ));
+
+    let progress = common::progress_bar(entries.len());
+    for entry in entries {
+        let data = archive.entry_data(entry)?;
+        let path = dir.join(entry.name_hash.to_string());
+        std::fs::write(&path, data)
+            .map_err(|e| CliError::io(format!("failed to write {}", path.display()), e))?;
+        progress.inc(1);
+    }
+    progress.finish_and_clear();
+
+    Ok(())
+}
+
+/// Maps the name hash of each file in `input` to its path.
+///
+/// Files are expected to be named after their hash, optionally with an extension
+/// (e.g. `.bin` from `bar extract`); others are hashed from their relative path.
+fn hashed_inputs(input: &Path) -> Result<HashMap<i32, PathBuf>, CliError> {
+    let files = common::collect_input_files(input)?;
+
+    Ok(files
+        .into_iter()
+        .map(|(abs_path, rel_path, hash)| {
+            let hash = rel_path
+                .file_stem()
+                .and_then(|stem| common::parse_hash(&stem.to_string_lossy()).ok())
+                .unwrap_or(hash);
+            (hash.0, abs_path)
+        })
+        .collect())
+}
+
+/// Writes the `--report` file, pairing each mapped file in `output_dir` with its
+/// input by name hash.
+fn write_report(
+    report: &Path,
+    output_dir: &Path,
+    sources: &HashMap<i32, PathBuf>,
+    mapped: usize,
+    not_found: &[PathBuf],
+) -> Result<(), CliError> {
+    let mut mappings = Vec::new();
+    for entry in walkdir::WalkDir::new(output_dir).sort_by_file_name() {
+        let entry = entry.map_err(|e| CliError::io("failed to read output folder", e.into()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let rel_path = entry
+            .path()
+            .strip_prefix(output_dir)
+            .map_err(|e| format!("failed to get relative path: {e}"))?;
+        let hash = common::hash_path(&rel_path.to_string_lossy());
+        if let Some(source) = sources.get(&hash.0) {
+            mappings.push((source.as_path(), entry.path().to_path_buf()));
+        }
+    }
+
+    let contents = if report
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+    {
+        let mut csv = String::from("status,source,destination\n");
+        for (source, destination) in &mappings {
+            csv += &format!(
+                "mapped,{},{}\n",
+                csv_field(&source.to_string_lossy()),
+                csv_field(&destination.to_string_lossy())
+            );
+        }
+        for source in not_found {
+            csv += &format!("not_found,{},\n", csv_field(&source.to_string_lossy()));
+        }
+        csv
+    } else {
+        let mappings: Vec<_> = mappings
+            .iter()
+            .map(|(source, destination)| {
+                serde_json::json!({ "source": source, "destination": destination })
+            })
+            .collect();
+        let json = serde_json::json!({
+            "mapped": mapped,
+            "mappings": mappings,
+            "not_found": not_found,
+        });
+        serde_json::to_string_pretty(&json)
+            .map_err(|e| format!("failed to serialize report: {e}"))?
+    };
+
+    common::create_output_file(report)?
+        .write_all(contents.as_bytes())
+        .map_err(|e| CliError::io(format!("failed to write report {}", report.display()), e))?;
+
+    common::log_info(&format!("Wrote mapping report to {}", report.display()));
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
 }
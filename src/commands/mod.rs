@@ -1,39 +1,157 @@
 use crate::commands::{
-    bar::Bar, compress::Compress, crypt::Crypt, map::Map, sdat::Sdat, sharc::Sharc,
+    bar::Bar, checksum::Checksum, compress::Compress, convert::Convert, crypt::Crypt, diff::Diff,
+    extract_one::ExtractOne, hash::Hash, identify::Identify, list::List, map::Map, repack::Repack,
+    sdat::Sdat, sharc::Sharc, verify::Verify, versions::Versions,
 };
 
+use crate::error::CliError;
+use hdk_archive::structs::CompressionType;
 use hdk_secure::hash::AfsHash;
+use serde::Deserialize;
 use smallvec::SmallVec;
 
-use std::path::PathBuf;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use enum_dispatch::enum_dispatch;
 
 pub mod bar;
+pub mod checksum;
 pub mod common;
 pub mod compress;
+pub mod convert;
 pub mod crypt;
+pub mod diff;
+pub mod extract_one;
+pub mod hash;
+pub mod identify;
+pub mod list;
+pub mod manifest;
 pub mod map;
 pub mod pkg;
+pub mod repack;
 pub mod sdat;
 pub mod sharc;
+pub mod summary;
+pub mod verify;
+pub mod versions;
 
 /// CLI for the `hdk-rs` PlayStation Home development kit.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Main {
+    #[clap(flatten)]
+    pub global: GlobalArgs,
+
     /// Command to run
     #[command(subcommand)]
     pub command: crate::commands::Command,
 }
 
+/// Options that apply to every command.
+#[derive(Args, Debug, Default)]
+pub struct GlobalArgs {
+    /// Print additional diagnostic information: `-v` for a line per entry, `-vv` to also time each one
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only print errors, and the data a command was asked for
+    #[clap(
+        short,
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "verbose"
+    )]
+    pub quiet: bool,
+
+    /// Directory for intermediate files (defaults to the system temp directory)
+    #[clap(long, global = true)]
+    pub tmp_dir: Option<PathBuf>,
+
+    /// Append a timestamped record of each run and its errors to this file
+    #[clap(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Print results and errors as JSON instead of text
+    #[clap(long, global = true, default_value_t = false)]
+    pub json: bool,
+
+    /// When done, print a JSON summary of the run to stdout, or to PATH if given
+    #[clap(long, global = true, value_name = "PATH", num_args = 0..=1, default_missing_value = "-")]
+    pub summary_json: Option<PathBuf>,
+
+    /// Load named keys from a TOML or JSON keyset file, for use as `--key @name`
+    #[clap(long, global = true, value_name = "PATH")]
+    pub keyset: Option<PathBuf>,
+
+    /// Number of threads for parallel work, unless a command's own option overrides it (0 = one per CPU core)
+    #[clap(long, global = true, value_name = "N")]
+    pub threads: Option<usize>,
+
+    /// Overwrite existing outputs without asking
+    #[clap(short, long, global = true, default_value_t = false)]
+    pub yes: bool,
+}
+
+static GLOBAL_ARGS: OnceLock<GlobalArgs> = OnceLock::new();
+
+/// Stores the global options for this invocation.
+///
+/// Must be called once, before dispatching the command.
+pub fn set_globals(args: GlobalArgs) {
+    let _ = GLOBAL_ARGS.set(args);
+}
+
+/// Returns the global options for this invocation.
+pub fn globals() -> &'static GlobalArgs {
+    GLOBAL_ARGS.get_or_init(GlobalArgs::default)
+}
+
+/// Appends a timestamped line to the `--log-file`, if one was given.
+pub fn log_to_file(message: &str) {
+    let Some(path) = &globals().log_file else {
+        return;
+    };
+
+    let line = format!(
+        "[{}] {message}\n",
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+
+    if let Err(e) = result {
+        eprintln!("Warning: failed to write log file {}: {e}", path.display());
+    }
+}
+
+/// Prints a command's error, and records it in the `--log-file`.
+pub fn report_error(error: &CliError) {
+    let message = error.to_string();
+    if globals().json {
+        eprintln!("{}", serde_json::json!({ "error": message }));
+    } else {
+        eprintln!("Error: {message}");
+    }
+    log_to_file(&format!("Error: {message}"));
+    summary::record_failure(&message, error.exit_code());
+}
+
 /// Trait for executing commands.
 ///
-/// Each command enum implements this trait to provide its execution logic.
+/// Each command enum implements this trait to provide its execution logic. An
+/// error returned from `execute` is reported by `main`, which exits with its code.
 #[enum_dispatch]
 pub trait Execute {
-    fn execute(self);
+    fn execute(self) -> Result<(), CliError>;
 }
 
 /// All of the available commands.
@@ -68,6 +186,46 @@ pub enum Command {
     /// PKG file operations
     #[command(subcommand)]
     Pkg(pkg::Pkg),
+
+    /// Detect the type of a file, or of every file in a folder
+    #[command(alias = "id")]
+    Identify(Identify),
+
+    /// List the entries of a SHARC or BAR archive without extracting it
+    #[command(alias = "ls")]
+    List(List),
+
+    /// Print the name hashes of archive paths
+    #[command()]
+    Hash(Hash),
+
+    /// Print the AfsHash, SHA-1 or SHA-256 digest of files
+    #[command()]
+    Checksum(Checksum),
+
+    /// Decode every entry of an archive, optionally comparing it against its source file
+    #[command()]
+    Verify(Verify),
+
+    /// Extract a single entry of an archive, by name hash, to a file or stdout
+    #[command()]
+    ExtractOne(ExtractOne),
+
+    /// Compare the entries of two archives, e.g. a repack against the original
+    #[command()]
+    Diff(Diff),
+
+    /// Rewrite a SHARC or BAR archive with a different compression, byte order or key
+    #[command()]
+    Repack(Repack),
+
+    /// Convert a SHARC archive to a BAR, or a BAR to a SHARC
+    #[command()]
+    Convert(Convert),
+
+    /// Print the versions of the hdk-rs libraries this binary was built against
+    #[command()]
+    Versions(Versions),
 }
 
 #[derive(Args, Debug)]
@@ -78,7 +236,7 @@ pub struct Input {
 }
 
 /// Common input/output arguments for commands.
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone, Default)]
 pub struct IOArgs {
     /// Input file / folder path
     #[clap(short, long)]
@@ -89,6 +247,260 @@ pub struct IOArgs {
     pub output: PathBuf,
 }
 
+/// Input/output arguments for commands that can process several inputs in one run.
+#[derive(Args, Debug, Clone)]
+pub struct BatchIOArgs {
+    /// Input file path, or `-` for stdin. Give several to process them all, writing
+    /// into the `--output` folder
+    #[clap(short, long, num_args = 1.., required_unless_present = "input_glob")]
+    pub input: Vec<PathBuf>,
+
+    /// Process every file matching this glob (e.g. `assets/*.sharc`), writing into the
+    /// `--output` folder
+    #[clap(long, value_name = "PATTERN", conflicts_with = "input")]
+    pub input_glob: Option<String>,
+
+    /// Output file / folder path; with several inputs, the folder to write into
+    #[clap(short, long)]
+    pub output: PathBuf,
+}
+
+impl BatchIOArgs {
+    /// Whether more than one input may be processed, so `--output` is a folder.
+    pub const fn is_batch(&self) -> bool {
+        self.input.len() > 1 || self.input_glob.is_some()
+    }
+
+    /// Pairs each input with its output path.
+    ///
+    /// A single input writes to `--output` itself. In a batch, each input writes to
+    /// `--output/<output_name(input)>`, and existing outputs are confirmed all at once.
+    pub fn resolve(&self, output_name: impl Fn(&Path) -> PathBuf) -> Result<Vec<IOArgs>, CliError> {
+        if !self.is_batch() {
+            return Ok(vec![IOArgs {
+                input: self.input[0].clone(),
+                output: self.output.clone(),
+            }]);
+        }
+
+        let inputs = match &self.input_glob {
+            Some(pattern) => {
+                let inputs = glob::glob(pattern)
+                    .map_err(|e| {
+                        CliError::InvalidArgument(format!("invalid glob '{pattern}': {e}"))
+                    })?
+                    .filter_map(Result::ok)
+                    .filter(|path| path.is_file())
+                    .collect::<Vec<_>>();
+                if inputs.is_empty() {
+                    return Err(CliError::InvalidArgument(format!(
+                        "no files match '{pattern}'"
+                    )));
+                }
+                inputs
+            }
+            None => self.input.clone(),
+        };
+
+        let mut outputs = std::collections::HashMap::new();
+        let mut pairs = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            if common::is_stdio(&input) {
+                return Err(CliError::InvalidArgument(
+                    "`-` can only be used as the only input".to_string(),
+                ));
+            }
+
+            let output = self.output.join(output_name(&input));
+            if let Some(other) = outputs.insert(output.clone(), input.clone()) {
+                return Err(CliError::InvalidArgument(format!(
+                    "{} and {} would both be written to {}",
+                    other.display(),
+                    input.display(),
+                    output.display()
+                )));
+            }
+            pairs.push(IOArgs { input, output });
+        }
+
+        Ok(pairs)
+    }
+}
+
+/// Common arguments for archive creation commands.
+#[derive(Args, Debug)]
+pub struct CreateArgs {
+    /// Input folder path
+    #[clap(short, long, required_unless_present_any = ["from_list", "manifest_in"])]
+    pub input: Option<PathBuf>,
+
+    /// Output file path
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Read `source_path<TAB>archive_name` lines from a file (or `-` for stdin)
+    /// instead of walking `--input`
+    #[clap(long, conflicts_with = "input")]
+    pub from_list: Option<PathBuf>,
+
+    /// Build the archive from a manifest giving each entry's source, name,
+    /// compression and IV, in the order they should be written
+    ///
+    /// Either JSON, or plain text with one `name = source` line per entry.
+    #[clap(long, alias = "manifest", conflicts_with_all = ["input", "from_list"])]
+    pub manifest_in: Option<PathBuf>,
+
+    /// Fail instead of warning when two input files share the same name hash
+    #[clap(long, default_value_t = false)]
+    pub strict: bool,
+
+    /// Store entries that grow when compressed uncompressed instead
+    #[clap(long, default_value_t = false)]
+    pub auto_raw: bool,
+
+    /// Show what would be written, without touching the filesystem
+    #[clap(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Record each input file's modification time in `<output>.times` next to the
+    /// archive, for `extract --preserve-times`
+    #[clap(long, default_value_t = false)]
+    pub preserve_times: bool,
+
+    #[clap(flatten)]
+    pub reproducibility: ReproducibleArgs,
+
+    #[clap(flatten)]
+    pub compression: CompressionArgs,
+}
+
+/// Common arguments for archive extraction commands.
+#[derive(Args, Debug, Clone)]
+pub struct ExtractArgs {
+    #[clap(flatten)]
+    pub batch: BatchIOArgs,
+
+    /// The archive being extracted and its output folder, set for each input by
+    /// [`ExtractArgs::each_input`]
+    #[clap(skip)]
+    pub io: IOArgs,
+
+    /// Don't apply the archive's timestamp to the extracted files' modification times
+    #[clap(long, default_value_t = false)]
+    pub no_preserve_times: bool,
+
+    /// Give each file the modification time recorded in the `<archive>.times` file
+    /// written by `create --preserve-times`, instead of the archive's timestamp
+    #[clap(long, default_value_t = false, conflicts_with = "no_preserve_times")]
+    pub preserve_times: bool,
+
+    /// Extract into an existing output folder without confirming, handling
+    /// existing files one at a time according to `--overwrite`
+    #[clap(long, default_value_t = false)]
+    pub merge: bool,
+
+    /// Only extract the entry at this position in the archive's entry table (0-based)
+    #[clap(long, value_name = "N")]
+    pub entry_index: Option<usize>,
+
+    /// Number of threads to extract entries with (defaults to one per CPU core)
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Only extract entries matching this pattern (repeatable).
+    ///
+    /// Matches a prefix of the name hash, or a glob against the entry's path from `--names`.
+    #[clap(long = "filter", value_name = "PATTERN")]
+    pub filters: Vec<String>,
+
+    /// How to name the extracted files
+    #[clap(long, value_enum, default_value_t = NamingStrategy::Auto)]
+    pub naming: NamingStrategy,
+
+    /// Newline-delimited list of known archive paths, used to name extracted files
+    /// whose hash matches one of them
+    #[clap(long, required_if_eq("naming", "resolved-name"))]
+    pub names: Option<PathBuf>,
+
+    /// Split hash-named output files into subfolders named after their first N hex digits
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub shard: Option<u8>,
+
+    /// What to do with files that already exist when using `--merge`
+    #[clap(long, value_enum, default_value_t = OverwritePolicy::Prompt, requires = "merge")]
+    pub overwrite: OverwritePolicy,
+
+    /// When using `--merge`, only overwrite existing files if the archive's copy is
+    /// newer, or differs if there's no timestamp to compare
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "merge",
+        conflicts_with = "overwrite"
+    )]
+    pub overwrite_newer_only: bool,
+
+    /// Show what would be written, without touching the filesystem
+    #[clap(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Also extract entries that are themselves SHARC, BAR or SDAT archives, each into
+    /// a folder next to it named after the entry
+    #[clap(long, default_value_t = false)]
+    pub recursive: bool,
+
+    /// How many levels of nested archives `--recursive` extracts
+    #[clap(long, value_name = "N", default_value_t = 4, requires = "recursive")]
+    pub max_depth: u8,
+
+    /// Check that each entry decodes to the size recorded in the archive, failing the
+    /// command (after extracting the rest) if any doesn't
+    #[clap(long, default_value_t = false)]
+    pub check: bool,
+
+    /// Stop at the first entry that fails to decode, instead of extracting the rest and
+    /// failing the command at the end
+    #[clap(long, default_value_t = false)]
+    pub fail_fast: bool,
+}
+
+impl ExtractArgs {
+    /// Splits the arguments into one set per input archive.
+    ///
+    /// In a batch, each archive is extracted into a folder named after it, inside
+    /// `--output`.
+    pub fn each_input(&self) -> Result<Vec<Self>, CliError> {
+        let pairs = self.batch.resolve(|input| {
+            input
+                .file_stem()
+                .map_or_else(|| PathBuf::from("archive"), PathBuf::from)
+        })?;
+
+        if self.batch.is_batch() && !self.merge && !self.dry_run {
+            common::confirm_overwrite_all(pairs.iter().map(|io| io.output.as_path()))?;
+        }
+
+        Ok(pairs
+            .into_iter()
+            .map(|io| Self { io, ..self.clone() })
+            .collect())
+    }
+
+    /// The policy to apply to each extracted file.
+    ///
+    /// Without `--merge` the whole folder has already been confirmed, so files are
+    /// always overwritten.
+    pub const fn overwrite_policy(&self) -> OverwritePolicy {
+        if !self.merge {
+            OverwritePolicy::Always
+        } else if self.overwrite_newer_only {
+            OverwritePolicy::NewerOnly
+        } else {
+            self.overwrite
+        }
+    }
+}
+
 /// Common input arguments for commands that only require an input path.
 #[derive(Args, Debug)]
 pub struct IArg {
@@ -97,6 +509,74 @@ pub struct IArg {
     pub input: PathBuf,
 }
 
+/// Arguments for removing entries from an existing archive.
+#[derive(Args, Debug)]
+pub struct RemoveArgs {
+    #[clap(flatten)]
+    pub io: IOArgs,
+
+    /// Name hash of an entry to remove, as used for extracted file names (repeatable)
+    #[clap(long = "hash", value_parser = common::parse_hash)]
+    pub hashes: Vec<AfsHash>,
+
+    /// Glob pattern matched against the `--names` list, e.g. `textures/*.dds` (repeatable)
+    #[clap(long = "pattern", requires = "names")]
+    pub patterns: Vec<String>,
+
+    /// Newline-delimited list of known archive paths, used to resolve `--pattern`
+    #[clap(long)]
+    pub names: Option<PathBuf>,
+}
+
+/// Arguments for adding files to an existing archive.
+#[derive(Args, Debug)]
+pub struct AppendArgs {
+    #[clap(flatten)]
+    pub io: IOArgs,
+
+    /// Folder of files to add, named by their path relative to it
+    #[clap(short, long, value_name = "DIR")]
+    pub add: PathBuf,
+
+    /// Replace entries that already exist instead of failing
+    #[clap(long, default_value_t = false)]
+    pub replace: bool,
+
+    #[clap(flatten)]
+    pub compression: CompressionArgs,
+}
+
+/// How to handle an output file that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OverwritePolicy {
+    /// Overwrite the existing file
+    Always,
+    /// Keep the existing file
+    Never,
+    /// Ask for each existing file
+    Prompt,
+    /// Overwrite the existing file only if the entry is newer (`--overwrite-newer-only`)
+    #[value(skip)]
+    NewerOnly,
+}
+
+/// How extracted entries are named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NamingStrategy {
+    /// Use the embedded names if the archive has them, otherwise the name hash
+    Auto,
+    /// Name each file after its full name hash, e.g. `ABCDEF12`
+    FullHash,
+    /// Nest hash-named files under folders named after their first `--shard` hex digits (default 2)
+    Sharded,
+    /// Name each file after its position in the archive's entry table, e.g. `42`
+    Index,
+    /// Resolve name hashes to paths with the `--names` list, falling back to the hash
+    ResolvedName,
+    /// Use the names stored by `sharc create --embed-names`, falling back to the hash
+    Embedded,
+}
+
 /// Utility wrapping of Endianness for clap argument parsing.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum EndianArg {
@@ -113,6 +593,77 @@ impl From<EndianArg> for hdk_archive::structs::Endianness {
     }
 }
 
+/// How an archive entry is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionArg {
+    /// Stored as-is
+    #[value(alias = "stored")]
+    #[serde(alias = "stored")]
+    None,
+    /// Compressed
+    Compressed,
+    /// Compressed and encrypted
+    Encrypted,
+}
+
+/// `--reproducible`, shared by SHARC, BAR and SDAT `create`.
+#[derive(Args, Debug)]
+pub struct ReproducibleArgs {
+    /// Produce byte-identical output across runs: derive IVs from name hashes and,
+    /// without a `.time` file, use `SOURCE_DATE_EPOCH` (or 0) as the timestamp
+    #[clap(long, default_value_t = false)]
+    pub reproducible: bool,
+}
+
+/// How `create` picks each entry's compression, when there's no manifest.
+#[derive(Args, Debug)]
+pub struct CompressionArgs {
+    /// How to store entries
+    #[clap(long, value_enum, default_value_t = CompressionArg::Encrypted)]
+    pub compression: CompressionArg,
+
+    /// Only apply `--compression` to files with these extensions (e.g. `.xml,.lua`),
+    /// storing the rest as-is
+    #[clap(long, value_name = "EXTS", value_delimiter = ',')]
+    pub compress_ext: Option<Vec<String>>,
+}
+
+impl CompressionArgs {
+    /// Picks the compression for the entry at `rel_path`, logging the choice with `--verbose`.
+    pub fn pick(&self, rel_path: &Path) -> CompressionType {
+        let allowed = self.compress_ext.as_ref().is_none_or(|exts| {
+            rel_path.extension().is_some_and(|ext| {
+                exts.iter()
+                    .any(|allowed| ext.eq_ignore_ascii_case(allowed.trim_start_matches('.')))
+            })
+        });
+
+        let compression = if allowed {
+            self.compression.into()
+        } else {
+            CompressionType::None
+        };
+
+        common::log_entry(&format!(
+            "{}: {}",
+            rel_path.display(),
+            common::compression_name(compression)
+        ));
+        compression
+    }
+}
+
+impl From<CompressionArg> for CompressionType {
+    fn from(value: CompressionArg) -> Self {
+        match value {
+            CompressionArg::None => Self::None,
+            CompressionArg::Compressed => Self::Compressed,
+            CompressionArg::Encrypted => Self::Encrypted,
+        }
+    }
+}
+
 /// Archive type parser
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum ArchiveType {
@@ -125,5 +676,7 @@ pub struct CompressedFile {
     rel_path: PathBuf,
     uncompressed_size: usize,
     compressed_data: SmallVec<[u8; 16_384]>, // Many entries are below this
+    compression: CompressionType,
     iv: [u8; 8],
+    grown_size: Option<usize>,
 }
@@ -1,51 +1,119 @@
-use clap::{Args, Subcommand};
+use clap::{Args, Subcommand, ValueEnum};
 use hdk_firmware::pkg::{PkgBuilder, PkgContentType, PkgDrmType, PkgPlatform, PkgReleaseType};
+use indicatif::ProgressBar;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use crate::commands::{Execute, IOArgs, Input, common};
+use crate::{
+    commands::{Execute, IOArgs, Input, common, sdat::SDAT_KEYS, summary},
+    error::CliError,
+    magic,
+};
 
 #[derive(Subcommand, Debug)]
 pub enum Pkg {
     /// Inspect a PlayStation 3 PKG file
     #[clap(alias = "i")]
-    Inspect(Input),
+    Inspect(PkgInspectArgs),
 
     /// Extract contents of a PlayStation 3 PKG file
     #[clap(alias = "x")]
-    Extract(IOArgs),
+    Extract(PkgExtractArgs),
 
     /// Create a PlayStation 3 PKG file from a directory
     #[clap(alias = "c")]
     Create(PkgCreateArgs),
+
+    /// Print only the content ID and title ID of a PlayStation 3 PKG file
+    #[clap(alias = "cid")]
+    ContentId(Input),
 }
 
 impl Execute for Pkg {
-    fn execute(self) {
-        let function = match self {
-            Self::Inspect(args) => Self::inspect(&args.input),
-            Self::Extract(args) => Self::extract(&args.input, &args.output),
+    fn execute(self) -> Result<(), CliError> {
+        match self {
+            Self::Inspect(args) => Self::inspect(&args),
+            Self::Extract(args) => Self::extract(&args),
             Self::Create(args) => Self::create(&args),
-        };
-
-        if let Err(e) = function {
-            eprintln!("Error: {}", e);
+            Self::ContentId(args) => Self::content_id(&args.input),
         }
     }
 }
 
 impl Pkg {
-    pub fn inspect(input: &PathBuf) -> Result<(), String> {
+    pub fn inspect(args: &PkgInspectArgs) -> Result<(), CliError> {
+        let input = &args.input;
+        summary::record_input(input);
+
         let file =
-            std::fs::File::open(input).map_err(|e| format!("failed to open PKG file: {e}"))?;
+            std::fs::File::open(input).map_err(|e| CliError::io("failed to open PKG file", e))?;
 
         let mut pkg = hdk_firmware::pkg::reader::PkgArchive::open(file)
-            .map_err(|e| format!("failed to read PKG file: {e}"))?;
+            .map_err(|e| CliError::Archive(format!("failed to read PKG file: {e}")))?;
+
+        let digest = if check_digest(input)? {
+            "valid"
+        } else {
+            "invalid"
+        };
+
+        // Checking the ECDSA signature needs Sony's public keys, which we don't ship
+        let signature = "unverifiable";
+
+        if crate::commands::globals().json {
+            let packets: Vec<_> = pkg
+                .metadata()
+                .packets
+                .iter()
+                .map(|packet| {
+                    serde_json::json!({
+                        "id": packet.id,
+                        "size": packet.data.len(),
+                        "data": hex::encode_upper(&packet.data),
+                        "decoded": describe_packet(packet.id, &packet.data),
+                    })
+                })
+                .collect();
+            let header = format!("{:?}", pkg.header());
+            let items: Vec<_> = pkg
+                .items()
+                .filter_map(|item| item.ok())
+                .map(|item| {
+                    serde_json::json!({
+                        "name": item.name,
+                        "flags": item.entry.flags,
+                        "size": item.entry.data_size,
+                    })
+                })
+                .collect();
+
+            println!(
+                "{}",
+                serde_json::json!({
+                    "header": header,
+                    "metadata": packets,
+                    "items": items,
+                    "digest": digest,
+                    "signature": signature,
+                })
+            );
+            return Self::check_integrity(args, digest);
+        }
 
         println!("PKG header: {:#?}", pkg.header());
 
         // Print every metadata packet
         println!("Metadata packets:");
         for packet in &pkg.metadata().packets {
+            if let Some(description) = describe_packet(packet.id, &packet.data) {
+                println!(
+                    "  ID: {:X}, size: {}, {description}",
+                    packet.id,
+                    packet.data.len()
+                );
+                continue;
+            }
+
             println!(
                 "  ID: {:X}, size: {}, data (hex): {}",
                 packet.id,
@@ -67,20 +135,163 @@ impl Pkg {
             );
         }
 
+        println!("digest: {digest}");
+        println!("signature: {signature} (no public keys available)");
+
+        Self::check_integrity(args, digest)
+    }
+
+    /// With `--verify`, turns a digest mismatch into an error.
+    fn check_integrity(args: &PkgInspectArgs, digest: &str) -> Result<(), CliError> {
+        if args.verify && digest != "valid" {
+            return Err(CliError::Decode(format!(
+                "{} failed verification: its SHA-1 digest doesn't match its contents",
+                args.input.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn content_id(input: &Path) -> Result<(), CliError> {
+        summary::record_input(input);
+
+        let file =
+            std::fs::File::open(input).map_err(|e| CliError::io("failed to open PKG file", e))?;
+
+        let pkg = hdk_firmware::pkg::reader::PkgArchive::open(file)
+            .map_err(|e| CliError::Archive(format!("failed to read PKG file: {e}")))?;
+
+        let content_id = String::from_utf8_lossy(pkg.header().content_id.as_ref())
+            .trim_end_matches('\0')
+            .to_string();
+
+        let title_id = title_id_of(&content_id)?;
+
+        if crate::commands::globals().json {
+            println!(
+                "{}",
+                serde_json::json!({ "content_id": content_id, "title_id": title_id })
+            );
+        } else {
+            println!("{content_id}");
+            println!("{title_id}");
+        }
+
         Ok(())
     }
 
-    pub fn extract(input: &Path, output: &Path) -> Result<(), String> {
+    pub fn extract(args: &PkgExtractArgs) -> Result<(), CliError> {
+        let (input, output) = (args.io.input.as_path(), args.io.output.as_path());
+        summary::record_input(input);
+
+        if common::is_stdio(input) {
+            return Err(CliError::InvalidArgument(
+                "PKG files are read in place and can't be read from stdin; save it to a file first"
+                    .to_string(),
+            ));
+        }
+
         let file =
-            std::fs::File::open(input).map_err(|e| format!("failed to open PKG file: {e}"))?;
+            std::fs::File::open(input).map_err(|e| CliError::io("failed to open PKG file", e))?;
 
         let mut pkg = hdk_firmware::pkg::reader::PkgArchive::open(file)
-            .map_err(|e| format!("failed to read PKG file: {e}"))?;
+            .map_err(|e| CliError::Archive(format!("failed to read PKG file: {e}")))?;
+
+        // Work out every output path up front, so `--list` shows exactly what
+        // a real run would write
+        let mut items: Vec<_> = pkg
+            .items()
+            .filter_map(|item| item.ok())
+            .map(|item| {
+                let output_path = output.join(&item.name);
+                (item, output_path)
+            })
+            .collect();
+        let item_count = items.len();
+
+        if let Some(index) = args.index {
+            if index >= items.len() {
+                return Err(CliError::InvalidArgument(format!(
+                    "item index {index} is out of range (the PKG has {} items)",
+                    items.len()
+                )));
+            }
+            items = vec![items.swap_remove(index)];
+        }
+
+        if !args.filters.is_empty() {
+            let patterns = args
+                .filters
+                .iter()
+                .map(|raw| {
+                    glob::Pattern::new(raw).map_err(|e| {
+                        CliError::InvalidArgument(format!("invalid filter '{raw}': {e}"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let total = items.len();
+            items.retain(|(item, _)| patterns.iter().any(|pattern| pattern.matches(&item.name)));
+            common::log_info(&format!("Filter matched {} of {total} items", items.len()));
+        }
+
+        items.retain(|(item, _)| {
+            let is_directory = item.entry.is_directory();
+            let kind_matches = match args.only {
+                Some(PkgItemKind::File) => !is_directory,
+                Some(PkgItemKind::Dir) => is_directory,
+                None => true,
+            };
+            let flags_match = args
+                .flags
+                .is_none_or(|mask| item.entry.flags & mask == mask);
+
+            kind_matches && flags_match && !(args.skip_dirs && is_directory)
+        });
+        let skipped = item_count - items.len();
+
+        if args.list {
+            let mut total_size = 0u64;
+            let mut file_count = 0usize;
+
+            for (item, output_path) in &items {
+                if item.entry.is_directory() {
+                    println!("{}/", output_path.display());
+                } else {
+                    let overwrites = if output_path.exists() {
+                        ", would overwrite"
+                    } else {
+                        ""
+                    };
+                    println!(
+                        "{} ({} bytes{overwrites})",
+                        output_path.display(),
+                        item.entry.data_size
+                    );
+                    total_size += item.entry.data_size;
+                    file_count += 1;
+                }
+            }
 
-        let items: Vec<_> = pkg.items().filter_map(|item| item.ok()).collect();
-        for item in items {
-            let output_path = output.join(&item.name);
+            println!(
+                "{file_count} files, {total_size} bytes would be written, {skipped} items skipped (nothing extracted)"
+            );
+            return Ok(());
+        }
 
+        // PKG items carry no digest of their own: the trailing SHA-1 covers all
+        // of their data, and each item's size is checked as it's streamed out
+        let mut failures = Vec::new();
+        if args.verify && !check_digest(input)? {
+            failures.push("the PKG's SHA-1 digest doesn't match its contents".to_string());
+        }
+
+        summary::record_output(output);
+        let extracted = items.len();
+        let progress = common::progress_bar(extracted);
+        for (item, output_path) in items {
+            progress.inc(1);
             if item.entry.is_directory() {
                 std::fs::create_dir_all(&output_path).map_err(|e| {
                     format!("failed to create directory {}: {e}", output_path.display())
@@ -95,26 +306,71 @@ impl Pkg {
                     })?;
                 }
 
-                let mut output_file = std::fs::File::create(&output_path)
-                    .map_err(|e| format!("failed to create file {}: {e}", output_path.display()))?;
+                let mut output_file = std::fs::File::create(&output_path).map_err(|e| {
+                    CliError::io(
+                        format!("failed to create file {}", output_path.display()),
+                        e,
+                    )
+                })?;
 
                 let mut data = pkg
                     .item_reader(item.index.try_into().unwrap())
-                    .map_err(|e| format!("failed to read item data: {e}"))?;
+                    .map_err(|e| CliError::Decode(format!("failed to read item data: {e}")))?;
 
-                std::io::copy(&mut data, &mut output_file)
-                    .map_err(|e| format!("failed to write file {}: {e}", output_path.display()))?;
+                let written = std::io::copy(&mut data, &mut output_file).map_err(|e| {
+                    CliError::io(format!("failed to write file {}", output_path.display()), e)
+                })?;
+                drop(output_file);
+                summary::record_entries(1, written);
+
+                if args.verify && written != item.entry.data_size {
+                    failures.push(format!(
+                        "{} extracted to {written} bytes, expected {}",
+                        item.name, item.entry.data_size
+                    ));
+                }
+
+                if args.decrypt_sdat && is_sdat_file(&output_path)? {
+                    decrypt_sdat_in_place(&output_path)?;
+                }
             }
         }
+        progress.finish_and_clear();
+
+        common::print_result(
+            &format!("Extracted {extracted} items, skipped {skipped}"),
+            &serde_json::json!({
+                "output": output,
+                "extracted": extracted,
+                "skipped": skipped,
+            }),
+        );
+
+        if failures.is_empty() {
+            return Ok(());
+        }
 
-        Ok(())
+        // Printed even with `--quiet`, since the output can't be trusted
+        for failure in &failures {
+            eprintln!("Verification failed: {failure}");
+        }
+        Err(CliError::Decode(format!(
+            "{} failed verification ({} mismatches)",
+            input.display(),
+            failures.len()
+        )))
     }
 
-    pub fn create(args: &PkgCreateArgs) -> Result<(), String> {
+    pub fn create(args: &PkgCreateArgs) -> Result<(), CliError> {
+        let start = std::time::Instant::now();
         let input = &args.input;
         let output = &args.output;
+        summary::record_input(input);
         if !input.is_dir() {
-            return Err(format!("input path {} is not a directory", input.display()));
+            return Err(CliError::InvalidArgument(format!(
+                "input path {} is not a directory",
+                input.display()
+            )));
         }
 
         let mut builder = hdk_firmware::pkg::writer::PkgBuilder::new()
@@ -124,7 +380,29 @@ impl Pkg {
             .drm_type(parse_drm_type(&args.drm_type)?)
             .content_id(&args.content_id)
             .title_id(&args.title_id)
-            .install_directory(&args.title_id);
+            .install_directory(args.install_directory.as_deref().unwrap_or(&args.title_id));
+
+        if args.dry_run {
+            let mut count = 0;
+            for entry in walkdir::WalkDir::new(input)
+                .min_depth(1)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(Result::ok)
+            {
+                let rel_path = entry
+                    .path()
+                    .strip_prefix(input)
+                    .unwrap_or_else(|_| entry.path());
+                let suffix = if entry.file_type().is_dir() { "/" } else { "" };
+                println!("Would add {}{suffix}", pkg_path_string(rel_path));
+                count += 1;
+            }
+
+            common::preview_output(output);
+            println!("Dry run: {count} entries, nothing was written");
+            return Ok(());
+        }
 
         fn pkg_path_string(path: &Path) -> String {
             let parts: Vec<String> = path
@@ -141,12 +419,13 @@ impl Pkg {
             builder: &mut PkgBuilder,
             base_path: &Path,
             rel_path: &Path,
-        ) -> Result<(), String> {
+            progress: &ProgressBar,
+        ) -> Result<(), CliError> {
             let full_path = base_path.join(rel_path);
 
             // Read directory entries
             let mut entries: Vec<_> = std::fs::read_dir(&full_path)
-                .map_err(|e| format!("failed to read directory: {e}"))?
+                .map_err(|e| CliError::io("failed to read directory", e))?
                 .filter_map(|e| e.ok())
                 .collect();
 
@@ -159,9 +438,11 @@ impl Pkg {
                     let entry_rel = rel_path.join(entry.file_name());
                     let entry_pkg = pkg_path_string(&entry_rel);
                     let data = std::fs::read(entry.path())
-                        .map_err(|e| format!("failed to read {}: {e}", entry_pkg))?;
+                        .map_err(|e| CliError::io(format!("failed to read {}", entry_pkg), e))?;
+                    summary::record_entries(1, data.len() as u64);
                     builder.add_file(&entry_pkg, data);
-                    println!("Added file: {}", entry_pkg);
+                    common::log_entry(&format!("Added file: {entry_pkg}"));
+                    progress.inc(1);
                 }
             }
 
@@ -171,8 +452,9 @@ impl Pkg {
                     let entry_rel = rel_path.join(entry.file_name());
                     let entry_pkg = pkg_path_string(&entry_rel);
                     builder.add_directory(&entry_pkg);
-                    println!("Added dir: {}", entry_pkg);
-                    add_directory_recursive(builder, base_path, &entry_rel)?;
+                    common::log_entry(&format!("Added dir: {entry_pkg}"));
+                    progress.inc(1);
+                    add_directory_recursive(builder, base_path, &entry_rel, progress)?;
                 }
             }
 
@@ -180,7 +462,13 @@ impl Pkg {
         }
 
         // Then call it:
-        add_directory_recursive(&mut builder, input, Path::new(""))?;
+        let entry_count = walkdir::WalkDir::new(input)
+            .min_depth(1)
+            .into_iter()
+            .count();
+        let progress = common::progress_bar(entry_count);
+        add_directory_recursive(&mut builder, input, Path::new(""), &progress)?;
+        progress.finish_and_clear();
 
         let output_file = common::create_output_file(output)?;
         let mut output_file = std::io::BufWriter::new(output_file);
@@ -189,11 +477,92 @@ impl Pkg {
             .write(&mut output_file)
             .map_err(|e| format!("failed to finalize PKG archive: {e}"))?;
 
-        println!("PKG archive created successfully: {}", output.display());
+        output_file
+            .flush()
+            .map_err(|e| CliError::io("failed to flush output file", e))?;
+        drop(output_file);
+
+        common::print_created(
+            &format!("PKG archive created successfully: {}", output.display()),
+            output,
+            start,
+        );
+
+        if !args.i_know {
+            warn_placeholder_identity(args);
+        }
+
         Ok(())
     }
 }
 
+/// Picks the title ID out of a content ID.
+///
+/// Content IDs look like `EP9000-RUST00005_00-...`: the nine-character title ID sits
+/// between the `-` after the service ID and the `_` before the version.
+fn title_id_of(content_id: &str) -> Result<&str, CliError> {
+    let bytes = content_id.as_bytes();
+    content_id
+        .get(7..16)
+        .filter(|title_id| {
+            bytes.get(6) == Some(&b'-')
+                && bytes.get(16) == Some(&b'_')
+                && title_id.bytes().all(|b| b.is_ascii_alphanumeric())
+        })
+        .ok_or_else(|| {
+            CliError::Archive(format!(
+                "content ID `{content_id}` doesn't contain a title ID (expected e.g. `{DEFAULT_CONTENT_ID}`)"
+            ))
+        })
+}
+
+const DEFAULT_CONTENT_ID: &str = "EP9000-RUST00005_00-RUST000000000001";
+const DEFAULT_TITLE_ID: &str = "RUST00005";
+const DEFAULT_RELEASE_TYPE: &str = "debug";
+const DEFAULT_DRM_TYPE: &str = "free";
+const DEFAULT_PLATFORM: &str = "ps3";
+const DEFAULT_CONTENT_TYPE: &str = "game_exec";
+
+/// Warns about every identity field of a new PKG that was left at its placeholder default.
+fn warn_placeholder_identity(args: &PkgCreateArgs) {
+    let fields = [
+        ("--content-id", &args.content_id, DEFAULT_CONTENT_ID),
+        ("--title-id", &args.title_id, DEFAULT_TITLE_ID),
+        ("--release-type", &args.release_type, DEFAULT_RELEASE_TYPE),
+        ("--drm-type", &args.drm_type, DEFAULT_DRM_TYPE),
+        ("--platform", &args.platform, DEFAULT_PLATFORM),
+        ("--content-type", &args.content_type, DEFAULT_CONTENT_TYPE),
+    ];
+
+    let defaults: Vec<_> = fields
+        .iter()
+        .filter(|(_, value, default)| value.eq_ignore_ascii_case(default))
+        .collect();
+    if defaults.is_empty() || crate::commands::globals().quiet {
+        return;
+    }
+
+    eprintln!();
+    eprintln!("WARNING: this PKG uses placeholder defaults for its identity:");
+    for (flag, value, _) in defaults {
+        eprintln!("  {flag} {value}");
+    }
+    eprintln!(
+        "Set them explicitly before distributing it, or pass `--i-know` to hide this warning."
+    );
+}
+
+#[derive(Args, Debug)]
+pub struct PkgInspectArgs {
+    /// Input PKG file path
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// Exit with an error if the PKG's digest doesn't match its contents
+    #[clap(long, default_value_t = false)]
+    pub verify: bool,
+}
+
 #[derive(Args, Debug)]
 pub struct PkgCreateArgs {
     /// Input folder path
@@ -205,62 +574,161 @@ pub struct PkgCreateArgs {
     pub output: PathBuf,
 
     /// PKG content ID
-    #[clap(long, default_value = "EP9000-RUST00005_00-RUST000000000001")]
+    #[clap(long, default_value = DEFAULT_CONTENT_ID)]
     pub content_id: String,
 
     /// PKG title ID
-    #[clap(long, default_value = "RUST00005")]
+    #[clap(long, default_value = DEFAULT_TITLE_ID)]
     pub title_id: String,
 
+    /// Directory the PKG installs to (defaults to the title ID)
+    #[clap(long)]
+    pub install_directory: Option<String>,
+
     /// PKG release type (debug, release)
-    #[clap(long, default_value = "debug")]
+    #[clap(long, default_value = DEFAULT_RELEASE_TYPE)]
     pub release_type: String,
 
     /// PKG DRM type (free, local, network, pspgo, none)
-    #[clap(long, default_value = "free")]
+    #[clap(long, default_value = DEFAULT_DRM_TYPE)]
     pub drm_type: String,
 
     /// PKG platform (ps3, psp)
-    #[clap(long, default_value = "ps3")]
+    #[clap(long, default_value = DEFAULT_PLATFORM)]
     pub platform: String,
 
     /// PKG content type (game_data, game_exec, ps1_emu, psp_minis, system_update, psp_remaster, psp_neogeo, avatar, minis2, xmb_plugin, theme, disc_movie, widget, license_file, pspgo)
-    #[clap(long, default_value = "game_exec")]
+    #[clap(long, default_value = DEFAULT_CONTENT_TYPE)]
     pub content_type: String,
+
+    /// Don't warn about identity fields left at their placeholder defaults
+    #[clap(long = "i-know", default_value_t = false)]
+    pub i_know: bool,
+
+    /// Show what would be written, without touching the filesystem
+    #[clap(long, default_value_t = false)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PkgExtractArgs {
+    #[clap(flatten)]
+    pub io: IOArgs,
+
+    /// Print the paths (and sizes) that would be extracted without writing anything
+    #[clap(long, alias = "dry-run", default_value_t = false)]
+    pub list: bool,
+
+    /// Decrypt SDAT items as they're extracted, writing the decrypted payload instead
+    #[clap(long, default_value_t = false)]
+    pub decrypt_sdat: bool,
+
+    /// Only extract the item at this position in the item list (0-based, as listed by `pkg inspect`)
+    #[clap(long, value_name = "N")]
+    pub index: Option<usize>,
+
+    /// Only extract items whose name matches this glob, e.g. `USRDIR/*.sdat` (repeatable)
+    #[clap(long = "filter", value_name = "PATTERN")]
+    pub filters: Vec<String>,
+
+    /// Don't recreate directory items (folders holding extracted files are still created)
+    #[clap(long, default_value_t = false)]
+    pub skip_dirs: bool,
+
+    /// Only extract items whose flags have every bit of this mask set, e.g. `0x3`
+    #[clap(long, value_name = "MASK", value_parser = parse_flags)]
+    pub flags: Option<u32>,
+
+    /// Only extract files or only directories
+    #[clap(long, value_enum)]
+    pub only: Option<PkgItemKind>,
+
+    /// Check the PKG's digest and that every item extracts to its recorded size,
+    /// exiting with an error if any check fails
+    #[clap(long, default_value_t = false)]
+    pub verify: bool,
+}
+
+/// The kind of a PKG item, for `pkg extract --only`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PkgItemKind {
+    /// Regular files
+    File,
+    /// Directories
+    Dir,
 }
 
-fn parse_release_type(value: &str) -> Result<PkgReleaseType, String> {
+/// Parses an item flags mask, as hex with a `0x` prefix or as decimal.
+fn parse_flags(value: &str) -> Result<u32, CliError> {
+    let parsed = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .map_or_else(|| value.parse(), |digits| u32::from_str_radix(digits, 16));
+
+    parsed.map_err(|e| CliError::InvalidArgument(format!("invalid flags mask '{value}': {e}")))
+}
+
+/// Checks whether an extracted file is an SDAT, without reading the whole file.
+fn is_sdat_file(path: &Path) -> Result<bool, CliError> {
+    let sample = magic::sample_file(path)
+        .map_err(|e| CliError::io(format!("failed to read file {}", path.display()), e))?;
+
+    Ok(magic::sdat_matcher(&sample))
+}
+
+/// Replaces an SDAT file on disk with its decrypted payload.
+fn decrypt_sdat_in_place(path: &Path) -> Result<(), CliError> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| CliError::io(format!("failed to open SDAT {}", path.display()), e))?;
+
+    let decrypted = hdk_sdat::SdatReader::open(file, &SDAT_KEYS)
+        .map_err(|e| CliError::Archive(format!("failed to open SDAT {}: {e}", path.display())))?
+        .decrypt_to_vec()
+        .map_err(|e| {
+            CliError::Archive(format!("failed to decrypt SDAT {}: {e}", path.display()))
+        })?;
+
+    std::fs::write(path, decrypted)
+        .map_err(|e| CliError::io(format!("failed to write file {}", path.display()), e))?;
+
+    common::log_info(&format!("Decrypted SDAT: {}", path.display()));
+    Ok(())
+}
+
+fn parse_release_type(value: &str) -> Result<PkgReleaseType, CliError> {
     match value.to_ascii_lowercase().as_str() {
         "debug" => Ok(PkgReleaseType::Debug),
         "release" => Ok(PkgReleaseType::Release),
-        _ => Err(format!(
+        _ => Err(CliError::InvalidArgument(format!(
             "invalid release type: {value} (expected: debug, release)"
-        )),
+        ))),
     }
 }
 
-fn parse_drm_type(value: &str) -> Result<PkgDrmType, String> {
+fn parse_drm_type(value: &str) -> Result<PkgDrmType, CliError> {
     match value.to_ascii_lowercase().as_str() {
         "free" => Ok(PkgDrmType::Free),
         "local" => Ok(PkgDrmType::Local),
         "network" => Ok(PkgDrmType::Network),
         "pspgo" => Ok(PkgDrmType::PspGo),
         "none" => Ok(PkgDrmType::None),
-        _ => Err(format!(
+        _ => Err(CliError::InvalidArgument(format!(
             "invalid DRM type: {value} (expected: free, local, network, pspgo, none)"
-        )),
+        ))),
     }
 }
 
-fn parse_platform(value: &str) -> Result<PkgPlatform, String> {
+fn parse_platform(value: &str) -> Result<PkgPlatform, CliError> {
     match value.to_ascii_lowercase().as_str() {
         "ps3" => Ok(PkgPlatform::PS3),
         "psp" => Ok(PkgPlatform::PSP),
-        _ => Err(format!("invalid platform: {value} (expected: ps3, psp)")),
+        _ => Err(CliError::InvalidArgument(format!(
+            "invalid platform: {value} (expected: ps3, psp)"
+        ))),
     }
 }
 
-fn parse_content_type(value: &str) -> Result<PkgContentType, String> {
+fn parse_content_type(value: &str) -> Result<PkgContentType, CliError> {
     match value.to_ascii_lowercase().as_str() {
         "game_data" => Ok(PkgContentType::GameData),
         "game_exec" => Ok(PkgContentType::GameExec),
@@ -277,8 +745,124 @@ fn parse_content_type(value: &str) -> Result<PkgContentType, String> {
         "widget" => Ok(PkgContentType::Widget),
         "license_file" => Ok(PkgContentType::LicenseFile),
         "pspgo" => Ok(PkgContentType::PspGo),
-        _ => Err(format!(
+        _ => Err(CliError::InvalidArgument(format!(
             "invalid content type: {value} (expected: game_data, game_exec, ps1_emu, psp_minis, system_update, psp_remaster, psp_neogeo, avatar, minis2, xmb_plugin, theme, disc_movie, widget, license_file, pspgo)"
+        ))),
+    }
+}
+
+/// Interprets the well-known metadata packets, or returns `None` to fall back to hex.
+///
+/// `hdk_firmware`'s `PkgDrmType` / `PkgContentType` only convert to their header
+/// values, so the values are mapped back here; names match `pkg create`'s options.
+fn describe_packet(id: u32, data: &[u8]) -> Option<String> {
+    let be_u32 = || data.get(..4)?.try_into().ok().map(u32::from_be_bytes);
+    // Versions are stored as BCD, e.g. `01 00` for 01.00
+    let version = |bytes: &[u8]| -> Option<String> {
+        match bytes {
+            [major, minor] => Some(format!("{major:02X}.{minor:02X}")),
+            _ => None,
+        }
+    };
+    let string = |bytes: &[u8]| {
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches('\0');
+        (!text.is_empty() && text.chars().all(|c| c.is_ascii_graphic() || c == ' '))
+            .then(|| text.to_string())
+    };
+
+    match id {
+        0x1 => {
+            let value = be_u32()?;
+            let name = match value {
+                0x1 => "network",
+                0x2 => "local",
+                0x3 => "free (no license)",
+                0x4 => "psp",
+                0x5 => "free",
+                _ => "unknown",
+            };
+            Some(format!("DRM type: {name} (0x{value:X})"))
+        }
+        0x2 => {
+            let value = be_u32()?;
+            let name = match value {
+                0x4 => "game_data",
+                0x5 => "game_exec",
+                0x6 => "ps1_emu",
+                0x7 => "psp",
+                0x9 => "theme",
+                0xA => "widget",
+                0xB => "license_file",
+                0xC => "xmb_plugin",
+                0xD => "avatar",
+                0xE => "pspgo",
+                0xF => "psp_minis",
+                0x10 => "psp_neogeo",
+                0x14 => "psp_remaster",
+                _ => "unknown",
+            };
+            Some(format!("content type: {name} (0x{value:X})"))
+        }
+        0x3 => Some(format!("package flags: 0x{:08X}", be_u32()?)),
+        0x4 => {
+            let size = u64::from_be_bytes(data.get(..8)?.try_into().ok()?);
+            Some(format!("package size: {size} bytes"))
+        }
+        0x5 => Some(format!(
+            "make_package_npdrm revision: {:02X}{:02X}, package version: {}",
+            data.first()?,
+            data.get(1)?,
+            version(data.get(2..4)?)?
+        )),
+        0x6 => Some(format!("title ID: {}", string(data)?)),
+        0x8 => Some(format!(
+            "system version: {}, package version: {}, app version: {}",
+            // Only the major and minor parts of the system version are shown
+            version(data.get(1..3)?)?,
+            version(data.get(4..6)?)?,
+            version(data.get(6..8)?)?
         )),
+        // The first 8 bytes are unknown; the directory follows, NUL-padded
+        0xA => Some(format!("install directory: {}", string(data.get(8..)?)?)),
+        _ => None,
+    }
+}
+
+/// Size of the footer at the end of every PKG, which starts with a SHA-1 of everything before it.
+const PKG_FOOTER_SIZE: u64 = 0x20;
+
+/// Checks a PKG's trailing SHA-1 digest against the rest of the file.
+fn check_digest(input: &Path) -> Result<bool, CliError> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file =
+        std::fs::File::open(input).map_err(|e| CliError::io("failed to open PKG file", e))?;
+    let len = file
+        .metadata()
+        .map_err(|e| CliError::io("failed to read PKG file", e))?
+        .len();
+    if len < PKG_FOOTER_SIZE {
+        return Ok(false);
     }
+
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut body = std::io::BufReader::new(&mut file).take(len - PKG_FOOTER_SIZE);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = body
+            .read(&mut buf)
+            .map_err(|e| CliError::io("failed to read PKG file", e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    let mut footer = [0u8; 20];
+    file.seek(SeekFrom::Start(len - PKG_FOOTER_SIZE))
+        .and_then(|_| file.read_exact(&mut footer))
+        .map_err(|e| CliError::io("failed to read PKG footer", e))?;
+
+    Ok(hasher.digest().bytes() == footer)
 }
@@ -0,0 +1,198 @@
+use std::io::Write;
+
+use binrw::Endian;
+use clap::Args;
+use hdk_archive::{
+    bar::builder::BarBuilder,
+    sharc::builder::SharcBuilder,
+    structs::{
+        ArchiveFlags, ArchiveFlagsValue, ArchiveVersion, CompressionType, Endianness, Entry,
+    },
+};
+use smallvec::SmallVec;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{
+    commands::{CompressionArg, EndianArg, Execute, IOArgs, common, summary},
+    error::CliError,
+    keys::{self, BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY, KeyArg, SHARC_DEFAULT_KEY, SHARC_FILES_KEY},
+    magic,
+};
+
+#[derive(Args, Debug)]
+pub struct Repack {
+    #[clap(flatten)]
+    pub io: IOArgs,
+
+    /// How to store entries in the new archive (defaults to keeping each entry's own)
+    #[clap(long, value_enum)]
+    pub compression: Option<CompressionArg>,
+
+    /// Byte order of the new archive (defaults to the input's)
+    #[clap(long, alias = "endian")]
+    pub endianness: Option<EndianArg>,
+
+    /// Key for the new SHARC archive, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+impl Execute for Repack {
+    fn execute(self) -> Result<(), CliError> {
+        self.repack()
+    }
+}
+
+impl Repack {
+    fn repack(&self) -> Result<(), CliError> {
+        let start = std::time::Instant::now();
+        let (input, output) = (self.io.input.as_path(), self.io.output.as_path());
+        summary::record_input(input);
+
+        let data = common::read_archive(input)?;
+        if magic::sdat_matcher(&data) {
+            return Err(CliError::InvalidArgument(format!(
+                "{} is an SDAT; unwrap it with `sdat extract` and recreate it instead",
+                input.display()
+            )));
+        }
+
+        let (kind, bytes) = match magic::extract_version(&data) {
+            Some(ArchiveVersion::SHARC) => ("SHARC", self.repack_sharc(&data)?),
+            Some(ArchiveVersion::BAR) => {
+                if self.key.is_some() {
+                    return Err(CliError::InvalidArgument(
+                        "--key can only be used with SHARC archives".to_string(),
+                    ));
+                }
+                ("BAR", self.repack_bar(&data)?)
+            }
+            _ => {
+                return Err(CliError::Archive(format!(
+                    "{} is not a SHARC or BAR archive",
+                    input.display()
+                )));
+            }
+        };
+
+        let mut output_file = common::create_output_file(output)?;
+        output_file
+            .write_all(&bytes)
+            .and_then(|()| output_file.flush())
+            .map_err(|e| CliError::io("failed to write output file", e))?;
+
+        common::print_created(
+            &format!("Repacked {kind} archive: {}", output.display()),
+            output,
+            start,
+        );
+        Ok(())
+    }
+
+    /// The compression for an entry that was stored with `compression`.
+    fn compression(&self, compression: CompressionType) -> CompressionType {
+        self.compression.map_or(compression, CompressionType::from)
+    }
+
+    /// The byte order of the new archive, given the input's.
+    fn endian(&self, endian: Endian) -> Endian {
+        self.endianness
+            .map_or(endian, |endianness| Endianness::from(endianness).into())
+    }
+
+    fn repack_sharc(&self, data: &[u8]) -> Result<Vec<u8>, CliError> {
+        let (sharc, endian) = common::read_sharc(data, SHARC_DEFAULT_KEY)?;
+
+        let mut archive_writer = SharcBuilder::new(
+            keys::resolve_or(self.key.as_ref(), SHARC_DEFAULT_KEY)?,
+            SHARC_FILES_KEY,
+        )
+        .with_timestamp(sharc.archive_data.timestamp);
+        let progress = common::progress_bar(sharc.entries.len());
+
+        // Entries are decoded and recompressed independently, so only adding them to
+        // the archive has to happen in order
+        let repack_entry = |entry: &Entry| {
+            let mut reader = std::io::Cursor::new(data);
+            let entry_data = sharc.entry_data(&mut reader, entry).map_err(|e| {
+                CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+            })?;
+
+            let iv = common::entry_iv(entry.name_hash, false);
+            let compressed = common::compress_sharc_entry(
+                &archive_writer,
+                entry.name_hash,
+                entry.name_hash.to_string().into(),
+                &entry_data,
+                self.compression(entry.location.1),
+                iv,
+                false,
+            )?;
+
+            progress.inc(1);
+            Ok::<_, CliError>(compressed)
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let compressed_data: Result<Vec<_>, CliError> =
+            sharc.entries.iter().map(repack_entry).collect();
+
+        #[cfg(feature = "rayon")]
+        let compressed_data: Result<Vec<_>, CliError> =
+            sharc.entries.par_iter().map(repack_entry).collect();
+
+        progress.finish_and_clear();
+
+        for file in compressed_data? {
+            summary::record_entries(1, file.uncompressed_size as u64);
+            archive_writer.add_compressed_entry(
+                file.name_hash,
+                file.compressed_data,
+                file.uncompressed_size as u32,
+                file.compression,
+                file.iv,
+            );
+        }
+
+        let mut buf = Vec::new();
+        archive_writer
+            .build(&mut std::io::Cursor::new(&mut buf), self.endian(endian))
+            .map_err(|e| format!("failed to finalize SHARC: {e}"))?;
+        Ok(buf)
+    }
+
+    fn repack_bar(&self, data: &[u8]) -> Result<Vec<u8>, CliError> {
+        let (archive, endian) = common::read_bar(data)?;
+
+        let mut archive_writer = BarBuilder::new(BAR_DEFAULT_KEY, BAR_SIGNATURE_KEY)
+            .with_flags(ArchiveFlags(ArchiveFlagsValue::Protected.into()))
+            .with_timestamp(archive.archive_data.timestamp);
+
+        let progress = common::progress_bar(archive.entries.len());
+        for entry in &archive.entries {
+            let mut reader = std::io::Cursor::new(data);
+            let entry_data = archive
+                .entry_data(&mut reader, entry, &BAR_DEFAULT_KEY, &BAR_SIGNATURE_KEY)
+                .map_err(|e| {
+                    CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+                })?;
+
+            summary::record_entries(1, entry_data.len() as u64);
+            archive_writer.add_entry(
+                entry.name_hash,
+                SmallVec::from_vec(entry_data),
+                self.compression(entry.location.1),
+            );
+            progress.inc(1);
+        }
+        progress.finish_and_clear();
+
+        let mut buf = Vec::new();
+        archive_writer
+            .build(&mut std::io::Cursor::new(&mut buf), self.endian(endian))
+            .map_err(|e| format!("failed to finalize archive: {e}"))?;
+        Ok(buf)
+    }
+}
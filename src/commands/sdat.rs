@@ -1,19 +1,22 @@
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
 
 use binrw::{BinRead, Endian};
-use clap::Subcommand;
-use rand::RngExt;
+use clap::{Args, Subcommand};
 
 use hdk_archive::{
     bar::structs::BarArchive,
     sharc::{builder::SharcBuilder, structs::SharcArchive},
-    structs::{ArchiveFlags, ArchiveFlagsValue, CompressionType, Endianness},
+    structs::{ArchiveFlags, ArchiveFlagsValue, ArchiveVersion, Endianness, Entry},
 };
+use hdk_secure::hash::AfsHash;
 
 use crate::{
-    commands::{ArchiveType, CompressedFile, EndianArg, Execute, IArg, IOArgs, common},
-    keys::{SHARC_FILES_KEY, SHARC_SDAT_KEY},
+    commands::{
+        ArchiveType, CompressedFile, CompressionArgs, EndianArg, Execute, ExtractArgs, IArg,
+        ReproducibleArgs, bar::Bar, common, common::InputSource, summary,
+    },
+    error::CliError,
+    keys::{self, KeyArg, SHARC_FILES_KEY, SHARC_SDAT_KEY},
     magic,
 };
 
@@ -24,36 +27,170 @@ use rayon::prelude::*;
 pub enum Sdat {
     /// Create an SDAT archive
     #[clap(alias = "c")]
-    Create {
-        /// Input directory to create SDAT from
-        #[clap(short, long)]
-        input: PathBuf,
-
-        /// Output SDAT file path
-        #[clap(short, long)]
-        output: PathBuf,
-
-        /// Archive type (SHARC or BAR) to wrap in SDAT (default: SHARC)
-        #[clap(short, long, default_value = "sharc")]
-        archive_type: ArchiveType,
-
-        /// Endianness for the inner SHARC/BAR archive (default: big-endian)
-        #[clap(short, long, default_value = "big")]
-        endian: EndianArg,
-
-        /// Whether to protect the inner SHARC/BAR archive
-        #[clap(short, long, default_value_t = false)]
-        protect: bool,
-    },
+    Create(SdatCreateArgs),
     /// Extract an SDAT archive
     #[clap(alias = "x")]
-    Extract(IOArgs),
+    Extract(SdatExtractArgs),
     /// Inspect an SDAT archive and print its contents
     #[clap(alias = "i")]
     Inspect(IArg),
 }
 
-const SDAT_KEYS: hdk_sdat::SdatKeys = hdk_sdat::SdatKeys {
+#[derive(Args, Debug)]
+pub struct SdatCreateArgs {
+    /// Input directory to create SDAT from
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// Output SDAT file path
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Archive type (SHARC or BAR) to wrap in SDAT (default: SHARC)
+    #[clap(short, long, default_value = "sharc")]
+    pub archive_type: ArchiveType,
+
+    /// Endianness for the inner SHARC/BAR archive (default: big-endian)
+    #[clap(short, long, alias = "endianness", default_value = "big")]
+    pub endian: EndianArg,
+
+    /// Whether to protect the inner SHARC/BAR archive
+    #[clap(short, long, default_value_t = false)]
+    pub protect: bool,
+
+    /// Derive entry IVs from each entry's name hash instead of generating them randomly.
+    ///
+    /// Repeated builds of the same input will produce byte-identical archives.
+    #[clap(long, default_value_t = false)]
+    pub deterministic: bool,
+
+    #[clap(flatten)]
+    pub reproducibility: ReproducibleArgs,
+
+    /// Record each input file's modification time in `<output>.times` next to the
+    /// SDAT, for `extract --preserve-times`
+    #[clap(long, default_value_t = false)]
+    pub preserve_times: bool,
+
+    /// Number of threads to compress entries with (defaults to one per CPU core)
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub compression_threads: Option<u16>,
+
+    #[clap(flatten)]
+    pub compression: CompressionArgs,
+
+    /// Show what would be written, without touching the filesystem
+    #[clap(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Key of the inner SHARC archive, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in SDAT key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+#[derive(Args, Debug)]
+pub struct SdatExtractArgs {
+    #[clap(flatten)]
+    pub extract: ExtractArgs,
+
+    /// Key of the inner SHARC archive, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in SDAT key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+
+    /// Check the NPD header's title hash before extracting, refusing files that fail
+    #[clap(long, default_value_t = false)]
+    pub verify: bool,
+
+    /// Extract even if `--verify` finds a mismatch
+    #[clap(long, default_value_t = false, requires = "verify")]
+    pub force: bool,
+}
+
+/// Decrypts the SHARC/BAR archive inside the SDAT read from `reader`.
+fn decrypt_sdat<R: std::io::Read + std::io::Seek>(reader: R) -> Result<Vec<u8>, CliError> {
+    let mut sdat = hdk_sdat::SdatReader::open(reader, &SDAT_KEYS)
+        .map_err(|e| CliError::Archive(format!("failed to open SDAT: {e}")))?;
+
+    sdat.decrypt_to_vec()
+        .map_err(|e| CliError::Archive(format!("failed to decrypt SDAT: {e}")))
+}
+
+/// Length of the NPD header at the start of every SDAT.
+const NPD_HEADER_LEN: usize = 0x80;
+
+/// Checks the title hash in an SDAT's NPD header, returning whether it matches.
+///
+/// The title hash is an AES-CMAC of the content ID followed by the file name, so a
+/// renamed or tampered file fails. The dev hash can't be checked for SDATs, since
+/// the data key is derived from it rather than it being a hash of anything.
+fn verify_npd_header(path: &Path) -> Result<bool, CliError> {
+    use cmac::{Cmac, Mac};
+    use std::io::Read;
+
+    let mut header = [0u8; NPD_HEADER_LEN];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|e| {
+            CliError::io(
+                format!("failed to read NPD header of {}", path.display()),
+                e,
+            )
+        })?;
+
+    if &header[..4] != b"NPD\0" {
+        return Err(CliError::Archive(format!(
+            "{} has no NPD header",
+            path.display()
+        )));
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let (content_id, title_hash) = (&header[0x10..0x40], &header[0x50..0x60]);
+
+    let mut mac = <Cmac<aes::Aes128> as Mac>::new_from_slice(&SDAT_KEYS.npdrm_omac_key_3)
+        .expect("AES-128 takes a 16-byte key");
+    mac.update(content_id);
+    mac.update(file_name.as_bytes());
+
+    Ok(mac.verify_slice(title_hash).is_ok())
+}
+
+/// Longest file name an SDAT can be created with.
+///
+/// The NPD header has no field for the file name: it only stores the title hash,
+/// a CMAC over the content ID and the name (see [`verify_npd_header`]), so the
+/// format itself puts no bound on the name's length. The console checks that hash
+/// against the name the file has on disk, though, so the name can't be longer
+/// than the PS3 filesystem allows, `CELL_FS_MAX_FS_FILE_NAME_LENGTH` (255 bytes).
+const SDAT_MAX_FILE_NAME_LEN: usize = 255;
+
+/// Derives the internal SDAT file name from the output path, validating it.
+fn sdat_file_name(output: &Path) -> Result<String, CliError> {
+    let file_name = output
+        .file_name()
+        .and_then(|s| s.to_str())
+        .ok_or("invalid output file name")?;
+
+    if !file_name.is_ascii() {
+        return Err(CliError::InvalidArgument(format!(
+            "SDAT file name `{file_name}` must only contain ASCII characters"
+        )));
+    }
+
+    if file_name.len() > SDAT_MAX_FILE_NAME_LEN {
+        return Err(CliError::InvalidArgument(format!(
+            "SDAT file name is {} characters long, but at most {SDAT_MAX_FILE_NAME_LEN} are allowed",
+            file_name.len()
+        )));
+    }
+
+    Ok(file_name.to_string())
+}
+
+pub const SDAT_KEYS: hdk_sdat::SdatKeys = hdk_sdat::SdatKeys {
     sdat_key: [
         0x0D, 0x65, 0x5E, 0xF8, 0xE6, 0x74, 0xA9, 0x8A, 0xB8, 0x50, 0x5C, 0xFA, 0x7D, 0x01, 0x29,
         0x33,
@@ -85,145 +222,282 @@ const SDAT_KEYS: hdk_sdat::SdatKeys = hdk_sdat::SdatKeys {
 };
 
 impl Execute for Sdat {
-    fn execute(self) {
-        let function = match self {
-            Self::Create {
-                input,
-                output,
-                archive_type,
-                endian,
-                protect,
-            } => Self::create(&input, &output, archive_type, endian, protect),
-            Self::Extract(args) => Self::extract(&args.input, &args.output),
+    fn execute(self) -> Result<(), CliError> {
+        match self {
+            Self::Create(args) => Self::create(&args),
+            Self::Extract(args) => {
+                let key = keys::resolve_or(args.key.as_ref(), SHARC_SDAT_KEY)?;
+                for extract in args.extract.each_input()? {
+                    if args.verify {
+                        if common::is_stdio(&extract.io.input) {
+                            return Err(CliError::InvalidArgument(
+                                "--verify can't be used with `--input -`: the title hash covers the file name"
+                                    .to_string(),
+                            ));
+                        }
+                        Self::verify(&extract.io.input, args.force)?;
+                    }
+                    Self::extract(&extract, key)?;
+                }
+                Ok(())
+            }
             Self::Inspect(args) => Self::inspect(&args.input),
-        };
-
-        if let Err(e) = function {
-            eprintln!("Error: {}", e);
         }
     }
 }
 
 impl Sdat {
-    pub fn create(
-        input: &Path,
-        output: &Path,
-        _archive_type: ArchiveType,
-        endian: EndianArg,
-        protect: bool,
-    ) -> Result<(), String> {
-        let endianess = Endianness::from(endian);
-        let flags = if protect {
+    pub fn create(args: &SdatCreateArgs) -> Result<(), CliError> {
+        let start = std::time::Instant::now();
+        let (input, output) = (args.input.as_path(), args.output.as_path());
+        summary::record_input(input);
+
+        // The file name is baked into the SDAT header, so check it before doing any work
+        let output_file_name = sdat_file_name(output)?;
+
+        // BAR archives use their own fixed keys, so a custom key would be silently ignored
+        if args.key.is_some() && args.archive_type != ArchiveType::Sharc {
+            return Err(CliError::InvalidArgument(
+                "--key can only be used with SHARC archives".to_string(),
+            ));
+        }
+
+        let endianess = Endianness::from(args.endian);
+        let flags = if args.protect {
             ArchiveFlags(ArchiveFlagsValue::Protected.into())
         } else {
             ArchiveFlags::default()
         };
 
-        let mut archive_writer =
-            SharcBuilder::new(SHARC_SDAT_KEY, SHARC_FILES_KEY).with_flags(flags);
-
         // Check if the input directory has a `.time` file for timestamp.
         // If so, parse as i32 and use it as the archive timestamp.
+        let mut timestamp = None;
         let time_path = input.join(".time");
         if time_path.exists() {
             let time_bytes = common::read_file_bytes(&time_path)
-                .map_err(|e| format!("failed to read .time file: {e}"))?;
+                .map_err(|e| CliError::io("failed to read .time file", e))?;
 
             if time_bytes.len() == 4 {
                 // Always read as BE
-                let timestamp = i32::from_be_bytes([
+                let time = i32::from_be_bytes([
                     time_bytes[0],
                     time_bytes[1],
                     time_bytes[2],
                     time_bytes[3],
                 ]);
-                archive_writer = archive_writer.with_timestamp(timestamp);
-                println!("Using timestamp from .time file: {}", timestamp);
+                timestamp = Some(time);
+                common::log_info(&format!("Using timestamp from .time file: {}", time));
             } else {
-                println!(
-                    "Warning: .time file has invalid length, using default timestamp (system time)."
+                common::log_info(
+                    "Warning: .time file has invalid length, using default timestamp (system time).",
                 );
             }
         }
 
-        let _ = common::create_output_file(output)?;
+        if timestamp.is_none() && args.reproducibility.reproducible {
+            timestamp = Some(common::reproducible_timestamp()?);
+        }
+
         let mut files = common::collect_input_files(input)?;
 
         // Sort by signed AfsHash value (ascending)
         files.sort_by_key(|a| a.2.0);
 
-        #[cfg(not(feature = "rayon"))]
-        let compressed_data: Vec<CompressedFile> = files
-            .into_iter()
-            .map(|(abs_path, rel_path, name_hash)| {
-                use hdk_archive::structs::CompressionType;
-
-                let iv = {
-                    let mut iv = [0u8; 8];
-                    let mut rng = rand::rng();
-                    rng.fill(&mut iv);
-                    iv
-                };
-
-                let data = common::read_file_bytes(&abs_path).expect("failed to read input file");
-                let compressed = archive_writer
-                    .compress_data(&data, CompressionType::Encrypted, &iv)
-                    .expect("failed to compress data");
-
-                CompressedFile {
-                    name_hash,
-                    rel_path,
-                    uncompressed_size: data.len(),
-                    compressed_data: compressed,
-                    iv,
-                }
+        if args.dry_run {
+            common::preview_create(
+                files
+                    .iter()
+                    .map(|(_, rel_path, hash)| (rel_path.as_path(), *hash)),
+                output,
+            );
+            return Ok(());
+        }
+        let _ = common::create_output_file(output)?;
+
+        // Entries don't store modification times, so they go in a sidecar file
+        let times = args
+            .preserve_times
+            .then(|| {
+                common::encode_time_list(
+                    files
+                        .iter()
+                        .map(|(abs_path, _, name_hash)| (abs_path.as_path(), *name_hash)),
+                )
             })
-            .collect::<Vec<_>>();
+            .transpose()?;
+
+        let buf = match args.archive_type {
+            ArchiveType::Sharc => Self::build_sharc(args, files, flags, timestamp, endianess)?,
+            ArchiveType::Bar => {
+                // BAR entries are compressed by the builder itself, with BAR's own keys
+                if args.compression_threads.is_some() {
+                    common::log_info(
+                        "Warning: `--compression-threads` only applies to SHARC archives.",
+                    );
+                }
+
+                let entries: Vec<_> = files
+                    .into_iter()
+                    .map(|(abs_path, rel_path, name_hash)| {
+                        common::log_entry(&format!(
+                            "Adding file: {} (hash: {})",
+                            rel_path.display(),
+                            name_hash
+                        ));
+                        let compression = args.compression.pick(&rel_path);
+                        (
+                            InputSource::File(abs_path),
+                            rel_path,
+                            name_hash,
+                            compression,
+                        )
+                    })
+                    .collect();
+
+                let buf = Bar::build(&entries, timestamp, flags, endianess.into())?;
+
+                let (archive, _) = common::read_bar(&buf)?;
+                let total_size = archive
+                    .entries
+                    .iter()
+                    .map(|entry| u64::from(entry.uncompressed_size))
+                    .sum();
+                summary::record_entries(archive.entries.len(), total_size);
+
+                buf
+            }
+        };
+
+        // Wrap the archive in SDAT
+        let sdat = hdk_sdat::SdatWriter::new(output_file_name, SDAT_KEYS)
+            .map_err(|e| format!("failed to create SDAT writer: {e}"))?;
+
+        let sdat_bytes = sdat
+            .write_to_vec(&buf)
+            .map_err(|e| CliError::Archive(format!("failed to write SDAT: {e}")))?;
+
+        // Write SDAT to output file
+        std::fs::write(output, &sdat_bytes)
+            .map_err(|e| CliError::io("failed to write output file", e))?;
+
+        if let Some(times) = times {
+            common::write_time_list(output, &times)?;
+        }
+
+        common::print_created(
+            &format!("Created SDAT archive: {}", output.display()),
+            output,
+            start,
+        );
+        Ok(())
+    }
+
+    /// Builds the SHARC archive an SDAT wraps, from `(absolute path, relative path, name hash)` files.
+    fn build_sharc(
+        args: &SdatCreateArgs,
+        files: Vec<(PathBuf, PathBuf, AfsHash)>,
+        flags: ArchiveFlags,
+        timestamp: Option<i32>,
+        endianess: Endianness,
+    ) -> Result<Vec<u8>, CliError> {
+        let mut archive_writer = SharcBuilder::new(
+            keys::resolve_or(args.key.as_ref(), SHARC_SDAT_KEY)?,
+            SHARC_FILES_KEY,
+        )
+        .with_flags(flags);
+        if let Some(timestamp) = timestamp {
+            archive_writer = archive_writer.with_timestamp(timestamp);
+        }
+        let progress = common::progress_bar(files.len());
+
+        #[cfg(not(feature = "rayon"))]
+        let compressed_data: Vec<CompressedFile> =
+            common::with_threads(args.compression_threads, "--compression-threads", || {
+                files
+                    .into_iter()
+                    .map(|(abs_path, rel_path, name_hash)| {
+                        let iv = common::entry_iv(
+                            name_hash,
+                            args.deterministic || args.reproducibility.reproducible,
+                        );
+                        let compression = args.compression.pick(&rel_path);
+
+                        let data = common::read_file_bytes(&abs_path).map_err(|e| {
+                            CliError::io(format!("failed to read file {}", abs_path.display()), e)
+                        })?;
+                        let file = common::compress_sharc_entry(
+                            &archive_writer,
+                            name_hash,
+                            rel_path,
+                            &data,
+                            compression,
+                            iv,
+                            false,
+                        )?;
+
+                        progress.inc(1);
+                        Ok(file)
+                    })
+                    .collect::<Result<Vec<_>, CliError>>()
+            })??;
 
         #[cfg(feature = "rayon")]
-        let compressed_data: Vec<CompressedFile> = files
-            .into_par_iter()
-            .map(|(abs_path, rel_path, name_hash)| {
-                use hdk_archive::structs::CompressionType;
-
-                let iv = {
-                    let mut iv = [0u8; 8];
-                    let mut rng = rand::rng();
-                    rng.fill(&mut iv);
-                    iv
-                };
-
-                let data = common::read_file_bytes(&abs_path).expect("failed to read input file");
-                let compressed = archive_writer
-                    .compress_data(&data, CompressionType::Encrypted, &iv)
-                    .expect("failed to compress data");
-
-                CompressedFile {
-                    name_hash,
-                    rel_path,
-                    uncompressed_size: data.len(),
-                    compressed_data: compressed,
-                    iv,
-                }
-            })
-            .collect();
+        let compressed_data: Vec<CompressedFile> =
+            common::with_threads(args.compression_threads, "--compression-threads", || {
+                common::report_parallelism();
+
+                files
+                    .into_par_iter()
+                    .map(|(abs_path, rel_path, name_hash)| {
+                        let iv = common::entry_iv(
+                            name_hash,
+                            args.deterministic || args.reproducibility.reproducible,
+                        );
+                        let compression = args.compression.pick(&rel_path);
+
+                        let data = common::read_file_bytes(&abs_path).map_err(|e| {
+                            CliError::io(format!("failed to read file {}", abs_path.display()), e)
+                        })?;
+                        let file = common::compress_sharc_entry(
+                            &archive_writer,
+                            name_hash,
+                            rel_path,
+                            &data,
+                            compression,
+                            iv,
+                            false,
+                        )?;
+
+                        progress.inc(1);
+                        Ok(file)
+                    })
+                    .collect::<Result<Vec<_>, CliError>>()
+            })??;
+
+        progress.finish_and_clear();
 
         for CompressedFile {
             name_hash,
             rel_path,
             uncompressed_size,
             compressed_data: compressed,
+            compression,
             iv,
+            grown_size: _,
         } in compressed_data
         {
-            println!("Adding file: {} (hash: {})", rel_path.display(), name_hash);
+            common::log_entry(&format!(
+                "Adding file: {} (hash: {})",
+                rel_path.display(),
+                name_hash
+            ));
+            summary::record_entries(1, uncompressed_size as u64);
 
             archive_writer.add_compressed_entry(
                 name_hash,
                 compressed,
                 uncompressed_size as u32,
-                // TODO: let user pick how to compress/encrypt files
-                CompressionType::Encrypted,
+                compression,
                 iv,
             );
         }
@@ -236,244 +510,108 @@ impl Sdat {
             .build(&mut writer, endianess.into())
             .map_err(|e| format!("failed to finalize SHARC: {e}"))?;
 
-        // Wrap SHARC in SDAT
-        let output_file_name = output
-            .file_name()
-            .and_then(|s| s.to_str())
-            .ok_or("invalid output file name")?
-            .to_string();
-
-        let sdat = hdk_sdat::SdatWriter::new(output_file_name, SDAT_KEYS)
-            .map_err(|e| format!("failed to create SDAT writer: {e}"))?;
-
-        let sdat_bytes = sdat
-            .write_to_vec(&buf)
-            .map_err(|e| format!("failed to write SDAT: {e}"))?;
-
-        // Write SDAT to output file
-        std::fs::write(output, &sdat_bytes)
-            .map_err(|e| format!("failed to write output file: {e}"))?;
-
-        println!("Created SDAT archive: {}", output.display());
-        Ok(())
+        Ok(buf)
     }
 
-    pub fn extract(input: &Path, output: &Path) -> Result<(), String> {
-        // Open and read the SDAT file
-        let file =
-            std::fs::File::open(input).map_err(|e| format!("failed to open input file: {e}"))?;
-
-        // Parse the SDAT file to extract the SHARC/BAR archive
-        let mut sdat = hdk_sdat::SdatReader::open(file, &SDAT_KEYS)
-            .map_err(|e| format!("failed to open SDAT: {e}"))?;
-
-        let archive_bytes = sdat
-            .decrypt_to_vec()
-            .map_err(|e| format!("failed to decrypt SDAT: {e}"))?;
-
-        // Try SHARC first, then BAR. If neither work, return error.
-        let magic: &[u8; 4] = &archive_bytes[0..4].try_into().unwrap();
-        let endian: Endian = magic::magic_to_endianess(magic).into();
-
-        // Share archive bytes across threads if rayon is enabled
-        let shared = Arc::new(archive_bytes);
-        let mut reader = std::io::Cursor::new(&shared[..]);
-
-        if let Ok(sharc) = match endian {
-            Endian::Little => {
-                SharcArchive::read_le_args(&mut reader, (SHARC_SDAT_KEY, shared.len() as u32))
-            }
-            Endian::Big => {
-                SharcArchive::read_be_args(&mut reader, (SHARC_SDAT_KEY, shared.len() as u32))
-            }
-        } {
-            common::create_output_dir(output)?;
-
-            #[cfg(not(feature = "rayon"))]
-            let results: Vec<(String, Vec<u8>)> = sharc
-                .entries
-                .iter()
-                .map(|entry| {
-                    let mut local_reader = std::io::Cursor::new(&shared[..]);
-                    let data = sharc
-                        .entry_data(&mut local_reader, entry)
-                        .expect("Failed to process entry");
-
-                    (entry.name_hash.to_string(), data)
-                })
-                .collect();
-
-            #[cfg(feature = "rayon")]
-            let results: Vec<(String, Vec<u8>)> = sharc
-                .entries
-                .par_iter()
-                .map(|entry| {
-                    let mut local_reader = std::io::Cursor::new(&shared[..]);
-                    let extracted_data = sharc
-                        .entry_data(&mut local_reader, entry)
-                        .expect("Failed to process entry");
-
-                    (entry.name_hash.to_string(), extracted_data)
-                })
-                .collect();
-
-            #[cfg(not(feature = "rayon"))]
-            {
-                for (rel, data) in results {
-                    let output_path = output.join(rel);
-                    std::fs::write(&output_path, &data).map_err(|e| {
-                        format!(
-                            "failed to write output file {}: {e}",
-                            &output_path.display()
-                        )
-                    })?;
-                }
-            }
-
-            #[cfg(feature = "rayon")]
-            results
-                .into_par_iter()
-                .try_for_each(|(rel, data)| {
-                    let output_path = output.join(rel);
-                    std::fs::write(output_path, &data)
-                })
-                .map_err(|e| e.to_string())?;
-
-            let time = sharc.archive_data.timestamp;
-            let time_path = output.join(".time");
-
-            std::fs::write(&time_path, time.to_be_bytes())
-                .map_err(|e| format!("failed to write .time file: {e}"))?;
-
-            println!(
-                "Extracted {} files to {}",
-                sharc.entries.len(),
-                output.display()
-            );
+    /// Checks an SDAT's NPD header, warning instead of failing if `force` is set.
+    fn verify(input: &Path, force: bool) -> Result<(), CliError> {
+        if verify_npd_header(input)? {
+            common::log_info(&format!("NPD header verified: {}", input.display()));
             return Ok(());
         }
 
-        // Try BAR
-        // Recreate reader from shared bytes for BAR parsing
-        let mut reader = std::io::Cursor::new(&shared[..]);
-        if let Ok(bar) = match endian {
-            Endian::Little => BarArchive::read_le_args(
-                &mut reader,
-                (
-                    crate::keys::BAR_DEFAULT_KEY,
-                    crate::keys::BAR_SIGNATURE_KEY,
-                    shared.len() as u32,
-                ),
-            ),
-            Endian::Big => BarArchive::read_be_args(
-                &mut reader,
-                (
-                    crate::keys::BAR_DEFAULT_KEY,
-                    crate::keys::BAR_SIGNATURE_KEY,
-                    shared.len() as u32,
-                ),
-            ),
-        } {
-            common::create_output_dir(output)?;
-
-            #[cfg(not(feature = "rayon"))]
-            {
-                for entry in &bar.entries {
-                    let mut local_reader = std::io::Cursor::new(&shared[..]);
-                    let data = bar
-                        .entry_data(
-                            &mut local_reader,
-                            entry,
-                            &crate::keys::BAR_DEFAULT_KEY,
-                            &crate::keys::BAR_SIGNATURE_KEY,
-                        )
-                        .map_err(|e| format!("failed to read BAR entry data: {e}"))?;
+        let mismatch = format!(
+            "NPD title hash mismatch in {}: the file was renamed, tampered with or built for another title",
+            input.display()
+        );
+        if !force {
+            return Err(CliError::Decode(format!(
+                "{mismatch}; pass --force to extract anyway"
+            )));
+        }
 
-                    let rel_path = entry.name_hash.to_string();
-                    let output_path = output.join(rel_path);
+        // Printed even with `--quiet`, since the output can't be trusted
+        eprintln!("WARNING: {mismatch}; extracting anyway because of --force");
+        Ok(())
+    }
 
-                    let mut output_file = std::fs::File::create(&output_path).map_err(|e| {
-                        format!(
-                            "failed to create output file {}: {e}",
-                            output_path.display()
-                        )
-                    })?;
+    pub fn extract(args: &ExtractArgs, key: [u8; 32]) -> Result<(), CliError> {
+        let input = args.io.input.as_path();
+        summary::record_input(input);
 
-                    std::io::copy(&mut &data[..], &mut output_file).map_err(|e| {
-                        format!("failed to write output file {}: {e}", output_path.display())
-                    })?;
-                }
-            }
+        // Piped input is buffered, since the SDAT reader needs to seek
+        let archive_bytes = if common::is_stdio(input) {
+            decrypt_sdat(std::io::Cursor::new(common::read_input(input)?))?
+        } else {
+            decrypt_sdat(
+                std::fs::File::open(input)
+                    .map_err(|e| CliError::io("failed to open input file", e))?,
+            )?
+        };
 
-            #[cfg(feature = "rayon")]
-            {
-                let results: Vec<(String, Vec<u8>)> = bar
-                    .entries
-                    .par_iter()
-                    .map(|entry| {
-                        let local = shared.clone();
-                        let mut local_reader = std::io::Cursor::new(&local[..]);
-                        let extracted_data = bar
-                            .entry_data(
-                                &mut local_reader,
-                                entry,
-                                &crate::keys::BAR_DEFAULT_KEY,
-                                &crate::keys::BAR_SIGNATURE_KEY,
-                            )
-                            .expect("Failed to process entry");
-                        (entry.name_hash.to_string(), extracted_data)
+        // Pick the reader from the archive header rather than trying each in turn,
+        // so a damaged archive reports why it failed to parse
+        match magic::extract_version(&archive_bytes) {
+            Some(ArchiveVersion::SHARC) => {
+                let (sharc, _) = common::read_sharc(&archive_bytes, key)?;
+
+                extract_entries(
+                    args,
+                    &sharc.entries,
+                    sharc.archive_data.timestamp,
+                    |entry| {
+                        sharc
+                            .entry_data(&mut std::io::Cursor::new(&archive_bytes[..]), entry)
+                            .map_err(|e| {
+                                CliError::Decode(format!(
+                                    "failed to read entry {}: {e}",
+                                    entry.name_hash
+                                ))
+                            })
+                    },
+                )
+            }
+            Some(ArchiveVersion::BAR) => {
+                let (bar, _) = common::read_bar(&archive_bytes)?;
+
+                extract_entries(args, &bar.entries, bar.archive_data.timestamp, |entry| {
+                    bar.entry_data(
+                        &mut std::io::Cursor::new(&archive_bytes[..]),
+                        entry,
+                        &crate::keys::BAR_DEFAULT_KEY,
+                        &crate::keys::BAR_SIGNATURE_KEY,
+                    )
+                    .map_err(|e| {
+                        CliError::Decode(format!(
+                            "failed to read BAR entry {}: {e}",
+                            entry.name_hash
+                        ))
                     })
-                    .collect();
-
-                for (rel, data) in results {
-                    let output_path = output.join(rel);
-                    let mut output_file = std::fs::File::create(&output_path).map_err(|e| {
-                        format!(
-                            "failed to create output file {}: {e}",
-                            output_path.display()
-                        )
-                    })?;
-
-                    std::io::copy(&mut &data[..], &mut output_file).map_err(|e| {
-                        format!("failed to write output file {}: {e}", output_path.display())
-                    })?;
-                }
+                })
             }
-
-            let time = bar.archive_data.timestamp;
-            let time_path = output.join(".time");
-
-            std::fs::write(&time_path, time.to_be_bytes())
-                .map_err(|e| format!("failed to write .time file: {e}"))?;
-
-            println!(
-                "Extracted {} files to {}",
-                bar.entries.len(),
-                output.display()
-            );
-
-            return Ok(());
+            _ => Err(CliError::Archive(
+                "file does not contain a supported SHARC or BAR archive".to_string(),
+            )),
         }
-
-        Err("file does not contain a supported SHARC or BAR archive".to_string())
     }
 
-    pub fn inspect(input: &Path) -> Result<(), String> {
+    pub fn inspect(input: &Path) -> Result<(), CliError> {
+        summary::record_input(input);
+
         // Open and read the SDAT file
         let file =
-            std::fs::File::open(input).map_err(|e| format!("failed to open input file: {e}"))?;
+            std::fs::File::open(input).map_err(|e| CliError::io("failed to open input file", e))?;
 
         // Parse the SDAT file to extract the SHARC/BAR archive
         let mut sdat = hdk_sdat::SdatReader::open(file, &SDAT_KEYS)
-            .map_err(|e| format!("failed to open SDAT: {e}"))?;
+            .map_err(|e| CliError::Archive(format!("failed to open SDAT: {e}")))?;
 
         let archive_bytes = sdat
             .decrypt_to_vec()
-            .map_err(|e| format!("failed to decrypt SDAT: {e}"))?;
+            .map_err(|e| CliError::Archive(format!("failed to decrypt SDAT: {e}")))?;
 
         // Try SHARC first
-        let magic: &[u8; 4] = &archive_bytes[0..4].try_into().unwrap();
-        let endian: Endian = magic::magic_to_endianess(magic).into();
+        let endian = common::archive_endian(&archive_bytes)?;
         let mut reader = std::io::Cursor::new(archive_bytes.clone());
 
         if let Ok(sharc) = match endian {
@@ -486,20 +624,7 @@ impl Sdat {
                 (SHARC_SDAT_KEY, archive_bytes.len() as u32),
             ),
         } {
-            let header = sharc.archive_data;
-            println!("Archive Type: SHARC");
-            println!("Timestamp: {}", header.timestamp);
-            println!("Entry Count: {}", sharc.entries.len());
-            println!("\nEntries:");
-            for entry in &sharc.entries {
-                println!(
-                    "  - Hash: {}, Offset: {}, Uncompressed Size: {}, Compressed Size: {}",
-                    entry.name_hash,
-                    entry.location.0,
-                    entry.uncompressed_size,
-                    entry.compressed_size
-                );
-            }
+            print_inspection("SHARC", sharc.archive_data.timestamp, &sharc.entries);
             return Ok(());
         }
 
@@ -522,23 +647,150 @@ impl Sdat {
                 ),
             ),
         } {
-            let header = bar.archive_data;
-            println!("Archive Type: BAR");
-            println!("Timestamp: {}", header.timestamp);
-            println!("Entry Count: {}", bar.entries.len());
-            println!("\nEntries:");
-            for entry in &bar.entries {
-                println!(
-                    "  - Hash: {}, Offset: {}, Uncompressed Size: {}, Compressed Size: {}",
-                    entry.name_hash,
-                    entry.location.0,
-                    entry.uncompressed_size,
-                    entry.compressed_size
-                );
-            }
+            print_inspection("BAR", bar.archive_data.timestamp, &bar.entries);
             return Ok(());
         }
 
-        Err("file does not contain a supported SHARC or BAR archive".to_string())
+        Err(CliError::Archive(
+            "file does not contain a supported SHARC or BAR archive".to_string(),
+        ))
+    }
+}
+
+/// Prints the header and entry table of an SDAT's inner `kind` archive.
+fn print_inspection(kind: &str, timestamp: i32, entries: &[Entry]) {
+    if crate::commands::globals().json {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "hash": entry.name_hash.to_string(),
+                    "offset": entry.location.0,
+                    "uncompressed_size": entry.uncompressed_size,
+                    "compressed_size": entry.compressed_size,
+                })
+            })
+            .collect();
+
+        println!(
+            "{}",
+            serde_json::json!({ "type": kind, "timestamp": timestamp, "entries": entries })
+        );
+        return;
+    }
+
+    println!("Archive Type: {kind}");
+    println!("Timestamp: {timestamp}");
+    println!("Entry Count: {}", entries.len());
+    println!("\nEntries:");
+    for entry in entries {
+        println!(
+            "  - Hash: {}, Offset: {}, Uncompressed Size: {}, Compressed Size: {}",
+            entry.name_hash, entry.location.0, entry.uncompressed_size, entry.compressed_size
+        );
+    }
+}
+
+/// Writes the `entries` of an SDAT's inner archive to the `--output` folder,
+/// reading each one's data with `read_entry`.
+fn extract_entries<F>(
+    args: &ExtractArgs,
+    entries: &[Entry],
+    time: i32,
+    read_entry: F,
+) -> Result<(), CliError>
+where
+    F: Fn(&Entry) -> Result<Vec<u8>, CliError> + Sync,
+{
+    let output = args.io.output.as_path();
+    let entries = common::extract_selection(args, entries)?;
+
+    if args.dry_run {
+        let namer = common::OutputNamer::new(args, "", None)?;
+        common::preview_extract(args, &namer, &entries);
+        return Ok(());
+    }
+    common::create_output_dir(output, args.merge)?;
+
+    let times = common::EntryTimes::load(args, time)?;
+    let overwrite = args.overwrite_policy();
+    let namer = common::OutputNamer::new(args, "", None)?;
+    let progress = common::progress_bar(entries.len());
+
+    // Returns the hash of an entry that failed to decode, unless `--fail-fast` is given
+    let extract_entry = |index: usize, entry: &Entry| {
+        let data = common::timed(
+            || format!("Decoding {}", entry.name_hash),
+            || read_entry(entry),
+        );
+        let Some(data) = common::salvage_entry(args, data)? else {
+            progress.inc(1);
+            return Ok(Some(entry.name_hash));
+        };
+        common::check_entry(args, entry, &data);
+        let output_path = namer.path(index, entry.name_hash);
+        common::write_extracted_file(&output_path, &data, times.get(entry.name_hash), overwrite)?;
+        common::extract_nested(args, &output_path, &data)?;
+
+        progress.inc(1);
+        Ok::<_, CliError>(None)
+    };
+
+    #[cfg(not(feature = "rayon"))]
+    let failed: Vec<AfsHash> = common::with_threads(args.jobs, "--jobs", || {
+        entries
+            .iter()
+            .filter_map(|&(index, entry)| extract_entry(index, entry).transpose())
+            .collect::<Result<_, _>>()
+    })??;
+
+    #[cfg(feature = "rayon")]
+    let failed: Vec<AfsHash> = common::with_threads(args.jobs, "--jobs", || {
+        common::report_parallelism();
+
+        entries
+            .par_iter()
+            .filter_map(|&(index, entry)| extract_entry(index, entry).transpose())
+            .collect::<Result<_, _>>()
+    })??;
+    progress.finish_and_clear();
+    namer.report();
+
+    std::fs::write(output.join(".time"), time.to_be_bytes())
+        .map_err(|e| CliError::io("failed to write .time file", e))?;
+    common::check_failed_entries(entries.len(), failed)?;
+
+    common::print_result(
+        &format!("Extracted {} files to {}", entries.len(), output.display()),
+        &serde_json::json!({ "output": output, "entries": entries.len() }),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sdat_file_name_accepts_longest_name() {
+        let name = format!("{}.sdat", "a".repeat(SDAT_MAX_FILE_NAME_LEN - 5));
+        assert_eq!(sdat_file_name(Path::new(&name)).unwrap(), name);
+    }
+
+    #[test]
+    fn sdat_file_name_rejects_over_long_name() {
+        let name = format!("{}.sdat", "a".repeat(SDAT_MAX_FILE_NAME_LEN - 4));
+        assert!(matches!(
+            sdat_file_name(Path::new(&name)),
+            Err(CliError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn sdat_file_name_rejects_non_ascii_name() {
+        assert!(matches!(
+            sdat_file_name(Path::new("objets_d\u{e9}cor.sdat")),
+            Err(CliError::InvalidArgument(_))
+        ));
     }
 }
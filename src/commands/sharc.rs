@@ -1,18 +1,22 @@
-use std::{io::Write, path::Path};
+use std::io::Write;
+use std::path::Path;
 
-use binrw::{BinRead, Endian};
-use clap::Subcommand;
-use rand::RngExt;
+use clap::{Args, Subcommand};
 
 use hdk_archive::{
-    sharc::{builder::SharcBuilder, structs::SharcArchive},
-    structs::{CompressionType, Endianness},
+    sharc::builder::SharcBuilder,
+    structs::{CompressionType, Endianness, Entry},
 };
+use hdk_secure::hash::AfsHash;
+use smallvec::SmallVec;
 
 use crate::{
-    commands::{CompressedFile, Execute, IOArgs, common},
-    keys::{SHARC_DEFAULT_KEY, SHARC_FILES_KEY},
-    magic,
+    commands::{
+        AppendArgs, CompressedFile, CreateArgs, EndianArg, Execute, ExtractArgs, IArg, RemoveArgs,
+        common, common::AppendEntry, manifest::Manifest, summary,
+    },
+    error::CliError,
+    keys::{self, KeyArg, SHARC_DEFAULT_KEY, SHARC_FILES_KEY},
 };
 
 #[cfg(feature = "rayon")]
@@ -22,134 +26,308 @@ use rayon::prelude::*;
 pub enum Sharc {
     /// Create a SHARC archive
     #[clap(alias = "c")]
-    Create(IOArgs),
+    Create(SharcCreateArgs),
     /// Extract a SHARC archive
     #[clap(alias = "x")]
-    Extract(IOArgs),
+    Extract(SharcExtractArgs),
+    /// Remove entries from a SHARC archive, writing the result to a new file
+    #[clap(alias = "rm")]
+    Remove(SharcRemoveArgs),
+    /// Add files to a SHARC archive, writing the result to a new file
+    #[clap(alias = "a")]
+    Append(SharcAppendArgs),
+    /// Print the header of a SHARC archive and a summary of its entries
+    #[clap(alias = "i")]
+    Inspect(IArg),
 }
 
-impl Execute for Sharc {
-    fn execute(self) {
-        let result = match self {
-            Self::Create(args) => Self::create(&args.input, &args.output),
-            Self::Extract(args) => Self::extract(&args.input, &args.output),
-        };
+#[derive(Args, Debug)]
+pub struct SharcCreateArgs {
+    #[clap(flatten)]
+    pub create: CreateArgs,
+
+    /// Derive entry IVs from each entry's name hash instead of generating them randomly.
+    ///
+    /// Repeated builds of the same input will produce byte-identical archives,
+    /// at the cost of reusing the same IV for an entry across builds.
+    #[clap(long, default_value_t = false)]
+    pub deterministic: bool,
+
+    /// Embed the original file names in the archive, so `sharc extract` can restore them
+    #[clap(long, default_value_t = false)]
+    pub embed_names: bool,
+
+    /// Number of threads to compress entries with (defaults to one per CPU core)
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub compression_threads: Option<u16>,
+
+    /// Byte order of the archive (default: big-endian)
+    #[clap(long, alias = "endian", default_value = "big")]
+    pub endianness: EndianArg,
+
+    /// Archive key, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+#[derive(Args, Debug)]
+pub struct SharcExtractArgs {
+    #[clap(flatten)]
+    pub extract: ExtractArgs,
 
-        if let Err(e) = result {
-            eprintln!("Error: {e}");
+    /// Archive key, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+#[derive(Args, Debug)]
+pub struct SharcRemoveArgs {
+    #[clap(flatten)]
+    pub remove: RemoveArgs,
+
+    /// Archive key, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+#[derive(Args, Debug)]
+pub struct SharcAppendArgs {
+    #[clap(flatten)]
+    pub append: AppendArgs,
+
+    /// Archive key, as 64 hex digits, `@name` from the keyset or `@path/to/keyfile` (defaults to the built-in key)
+    #[clap(short, long, value_parser = keys::parse_key)]
+    pub key: Option<KeyArg>,
+}
+
+impl Execute for Sharc {
+    fn execute(self) -> Result<(), CliError> {
+        match self {
+            Self::Create(args) => Self::create(
+                &args.create,
+                args.deterministic || args.create.reproducibility.reproducible,
+                args.embed_names,
+                args.compression_threads,
+                args.endianness,
+                keys::resolve_or(args.key.as_ref(), SHARC_DEFAULT_KEY)?,
+            ),
+            Self::Extract(args) => {
+                let key = keys::resolve_or(args.key.as_ref(), SHARC_DEFAULT_KEY)?;
+                for extract in args.extract.each_input()? {
+                    Self::extract(&extract, key)?;
+                }
+                Ok(())
+            }
+            Self::Remove(args) => Self::remove(&args),
+            Self::Append(args) => Self::append(&args),
+            Self::Inspect(args) => Self::inspect(&args.input),
         }
     }
 }
 
 impl Sharc {
-    pub fn create(input: &Path, output: &Path) -> Result<(), String> {
-        // TODO: let user pick endianness
-        let endianess = Endianness::Big;
+    pub fn create(
+        args: &CreateArgs,
+        deterministic: bool,
+        embed_names: bool,
+        compression_threads: Option<u16>,
+        endianness: EndianArg,
+        key: [u8; 32],
+    ) -> Result<(), CliError> {
+        let start = std::time::Instant::now();
+        let output = args.output.as_path();
+        let endianess = Endianness::from(endianness);
+
+        let mut archive_writer = SharcBuilder::new(key, SHARC_FILES_KEY);
+
+        let manifest = Manifest::from_args(args)?;
+
+        // Use the manifest's timestamp, or the input directory's `.time` file if it has one.
+        let mut timestamp = match (manifest.as_ref().and_then(|m| m.timestamp), &args.input) {
+            (Some(timestamp), _) => Some(timestamp),
+            (None, Some(input)) => common::read_time_file(input)?,
+            (None, None) => None,
+        };
+        if timestamp.is_none() && args.reproducibility.reproducible {
+            timestamp = Some(common::reproducible_timestamp()?);
+        }
+        if let Some(timestamp) = timestamp {
+            archive_writer = archive_writer.with_timestamp(timestamp);
+        }
 
-        let mut archive_writer = SharcBuilder::new(SHARC_DEFAULT_KEY, SHARC_FILES_KEY);
-        let mut output_file = common::create_output_file(output)?;
+        let mut files = match &manifest {
+            Some(manifest) => manifest.files(),
+            None => common::collect_create_inputs(args)?,
+        };
 
-        // Check if the input directory has a `.time` file for timestamp.
-        // If so, parse as i32 and use it as the archive timestamp.
-        let time_path = input.join(".time");
-        if time_path.exists() {
-            let time_bytes = common::read_file_bytes(&time_path)
-                .map_err(|e| format!("failed to read .time file: {e}"))?;
-
-            if time_bytes.len() == 4 {
-                // Always read as BE
-                let timestamp = i32::from_be_bytes([
-                    time_bytes[0],
-                    time_bytes[1],
-                    time_bytes[2],
-                    time_bytes[3],
-                ]);
-                archive_writer = archive_writer.with_timestamp(timestamp);
-                println!("Using timestamp from .time file: {}", timestamp);
-            } else {
-                println!(
-                    "Warning: .time file has invalid length, using default timestamp (system time)."
-                );
+        // Sort ascending by signed AfsHash value
+        // This ensures they're written in the same order as the input files.
+        // A manifest's order is kept as-is.
+        if manifest.is_none() {
+            files.sort_by_key(|(_, _, a_hash)| a_hash.0);
+        }
+        common::check_hash_collisions(&mut files, args.strict)?;
+
+        let names_hash = common::hash_path(common::EMBEDDED_NAMES_PATH);
+        let embedded_names = if embed_names {
+            if files
+                .iter()
+                .any(|(_, _, name_hash)| name_hash.0 == names_hash.0)
+            {
+                return Err(CliError::InvalidArgument(format!(
+                    "an input file uses the reserved name `{}`",
+                    common::EMBEDDED_NAMES_PATH
+                )));
             }
+            Some(common::encode_name_list(&files))
+        } else {
+            None
+        };
+
+        if args.dry_run {
+            common::preview_create(
+                files
+                    .iter()
+                    .map(|(_, rel_path, hash)| (rel_path.as_path(), *hash)),
+                output,
+            );
+            return Ok(());
         }
+        let mut output_file = common::create_output_file(output)?;
 
-        let mut files = common::collect_input_files(input)?;
+        // Entries don't store modification times, so they go in a sidecar file
+        let times = args
+            .preserve_times
+            .then(|| {
+                common::encode_time_list(
+                    files
+                        .iter()
+                        .filter_map(|(source, _, name_hash)| Some((source.path()?, *name_hash))),
+                )
+            })
+            .transpose()?;
 
-        // Sort ascending by signed AfsHash value
-        // This ensures they're written in the same order as the input files
-        files.sort_by_key(|(_, _, a_hash)| a_hash.0);
+        let auto_raw = args.auto_raw;
+        let progress = common::progress_bar(files.len());
 
         #[cfg(not(feature = "rayon"))]
-        let compressed_data: Vec<CompressedFile> = files
-            .into_iter()
-            .map(|(abs_path, rel_path, name_hash)| {
-                use hdk_archive::structs::CompressionType;
-
-                let iv = {
-                    let mut iv = [0u8; 8];
-                    let mut rng = rand::rng();
-                    rng.fill(&mut iv);
-                    iv
-                };
-
-                let data = common::read_file_bytes(&abs_path).expect("failed to read input file");
-                let compressed = archive_writer
-                    .compress_data(&data, CompressionType::Encrypted, &iv)
-                    .expect("failed to compress data");
-
-                CompressedFile {
-                    name_hash,
-                    rel_path,
-                    uncompressed_size: data.len(),
-                    compressed_data: compressed,
-                    iv,
-                }
-            })
-            .collect::<Vec<_>>();
+        let compressed_data: Vec<CompressedFile> =
+            common::with_threads(compression_threads, "--compression-threads", || {
+                files
+                    .into_iter()
+                    .map(|(source, rel_path, name_hash)| {
+                        let compression = manifest
+                            .as_ref()
+                            .and_then(|manifest| manifest.compression(name_hash))
+                            .unwrap_or_else(|| args.compression.pick(&rel_path));
+                        let iv = manifest.as_ref().map_or_else(
+                            || common::entry_iv(name_hash, deterministic),
+                            |manifest| manifest.iv(name_hash, deterministic),
+                        );
+
+                        let data = source.read()?;
+                        let file = common::compress_sharc_entry(
+                            &archive_writer,
+                            name_hash,
+                            rel_path,
+                            &data,
+                            compression,
+                            iv,
+                            auto_raw,
+                        )?;
+
+                        progress.inc(1);
+                        Ok(file)
+                    })
+                    .collect::<Result<Vec<_>, CliError>>()
+            })??;
 
         #[cfg(feature = "rayon")]
-        let compressed_data: Vec<CompressedFile> = files
-            .into_par_iter()
-            .map(|(abs_path, rel_path, name_hash)| {
-                use hdk_archive::structs::CompressionType;
-
-                let iv = {
-                    let mut iv = [0u8; 8];
-                    let mut rng = rand::rng();
-                    rng.fill(&mut iv);
-                    iv
-                };
-
-                let data = common::read_file_bytes(&abs_path).expect("failed to read input file");
-                let compressed = archive_writer
-                    .compress_data(&data, CompressionType::Encrypted, &iv)
-                    .expect("failed to compress data");
-
-                CompressedFile {
-                    name_hash,
-                    rel_path,
-                    uncompressed_size: data.len(),
-                    compressed_data: compressed,
-                    iv,
-                }
+        let compressed_data: Vec<CompressedFile> =
+            common::with_threads(compression_threads, "--compression-threads", || {
+                common::report_parallelism();
+
+                files
+                    .into_par_iter()
+                    .map(|(source, rel_path, name_hash)| {
+                        let compression = manifest
+                            .as_ref()
+                            .and_then(|manifest| manifest.compression(name_hash))
+                            .unwrap_or_else(|| args.compression.pick(&rel_path));
+                        let iv = manifest.as_ref().map_or_else(
+                            || common::entry_iv(name_hash, deterministic),
+                            |manifest| manifest.iv(name_hash, deterministic),
+                        );
+
+                        let data = source.read()?;
+                        let file = common::compress_sharc_entry(
+                            &archive_writer,
+                            name_hash,
+                            rel_path,
+                            &data,
+                            compression,
+                            iv,
+                            auto_raw,
+                        )?;
+
+                        progress.inc(1);
+                        Ok(file)
+                    })
+                    .collect::<Result<Vec<_>, CliError>>()
+            })??;
+        progress.finish_and_clear();
+
+        let grown: Vec<_> = compressed_data
+            .iter()
+            .filter_map(|file| {
+                file.grown_size
+                    .map(|size| (file.rel_path.as_path(), file.uncompressed_size, size))
             })
             .collect();
+        common::report_grown_entries(&grown, auto_raw);
 
         for CompressedFile {
             name_hash,
             rel_path,
             uncompressed_size,
             compressed_data: compressed,
+            compression,
             iv,
+            grown_size: _,
         } in compressed_data
         {
-            println!("Adding file: {} (hash: {})", rel_path.display(), name_hash);
+            common::log_entry(&format!(
+                "Adding file: {} (hash: {})",
+                rel_path.display(),
+                name_hash
+            ));
+            summary::record_entries(1, uncompressed_size as u64);
 
             archive_writer.add_compressed_entry(
                 name_hash,
                 compressed,
                 uncompressed_size as u32,
-                // TODO: let user pick how to compress/encrypt files
+                compression,
+                iv,
+            );
+        }
+
+        if let Some(names) = embedded_names {
+            common::log_entry(&format!(
+                "Adding file: {} (hash: {})",
+                common::EMBEDDED_NAMES_PATH,
+                names_hash
+            ));
+
+            let iv = common::entry_iv(names_hash, deterministic);
+            let compressed = archive_writer
+                .compress_data(&names, CompressionType::Encrypted, &iv)
+                .map_err(|e| CliError::Decode(format!("failed to compress embedded names: {e}")))?;
+
+            archive_writer.add_compressed_entry(
+                names_hash,
+                compressed,
+                names.len() as u32,
                 CompressionType::Encrypted,
                 iv,
             );
@@ -161,96 +339,301 @@ impl Sharc {
 
         output_file
             .flush()
-            .map_err(|e| format!("failed to flush output file: {e}"))?;
+            .map_err(|e| CliError::io("failed to flush output file", e))?;
 
-        println!("Created SHARC archive: {}", output.display());
+        if let Some(times) = times {
+            common::write_time_list(output, &times)?;
+        }
+
+        common::print_created(
+            &format!("Created SHARC archive: {}", output.display()),
+            output,
+            start,
+        );
         Ok(())
     }
 
-    pub fn extract(input: &Path, output: &Path) -> Result<(), String> {
-        #[cfg(not(feature = "memmap2"))]
-        let data = std::fs::read(input).map_err(|e| format!("failed to read input file: {e}"))?;
-
-        #[cfg(feature = "memmap2")]
-        let data = {
-            let file = std::fs::File::open(input)
-                .map_err(|e| format!("failed to open input file: {e}"))?;
-            unsafe {
-                memmap2::Mmap::map(&file)
-                    .map_err(|e| format!("failed to memory-map input file: {e}"))?
-            }
-        };
-
-        let data_len = data.len() as u32;
-
-        let mut magic = [0u8; 4];
-        magic.clone_from_slice(&data[0..4]);
+    pub fn extract(args: &ExtractArgs, key: [u8; 32]) -> Result<(), CliError> {
+        let (input, output) = (args.io.input.as_path(), args.io.output.as_path());
+        summary::record_input(input);
 
-        let mut reader = std::io::Cursor::new(&data);
+        let data = common::read_archive(input)?;
 
         // let mut archive_reader =
         //     hdk_archive::sharc::reader::SharcReader::open(file, crate::keys::SHARC_DEFAULT_KEY)
-        //         .map_err(|e| format!("failed to open SHARC archive: {e}"))?;
-
-        let endian: Endian = magic::magic_to_endianess(&magic).into();
-        let sharc = match endian {
-            Endian::Little => {
-                SharcArchive::read_le_args(&mut reader, (SHARC_DEFAULT_KEY, data_len))
-            }
-            Endian::Big => SharcArchive::read_be_args(&mut reader, (SHARC_DEFAULT_KEY, data_len)),
+        //         .map_err(|e| CliError::Archive(format!("failed to open SHARC archive: {e}")))?;
+
+        let (sharc, _) = common::read_sharc(&data, key)?;
+        let entries = common::extract_selection(args, &sharc.entries)?;
+
+        if args.dry_run {
+            // Only the embedded name list has to be decoded to know where entries would go
+            let names_hash = common::hash_path(common::EMBEDDED_NAMES_PATH);
+            let embedded = entries
+                .iter()
+                .find(|(_, entry)| entry.name_hash.0 == names_hash.0)
+                .map(|(_, entry)| sharc.entry_data(&mut std::io::Cursor::new(&data), entry))
+                .transpose()
+                .map_err(|e| CliError::Archive(format!("failed to read embedded names: {e}")))?
+                .map(|names| common::decode_name_list(&names));
+
+            let namer = common::OutputNamer::new(args, "", embedded)?;
+            common::preview_extract(args, &namer, &entries);
+            return Ok(());
         }
-        .map_err(|e| format!("failed to read SHARC archive: {e}"))?;
+        common::create_output_dir(output, args.merge)?;
+        let progress = common::progress_bar(entries.len());
+
+        // Entries that fail to decode come back as `None`, unless `--fail-fast` is given
+        let decode_entry = |entry: &Entry| {
+            // Each thread gets its own view of the data
+            let mut local_reader = std::io::Cursor::new(&data);
+
+            let extracted_data = common::timed(
+                || format!("Decoding {}", entry.name_hash),
+                || sharc.entry_data(&mut local_reader, entry),
+            )
+            .map_err(|e| {
+                CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+            });
+            let extracted_data = common::salvage_entry(args, extracted_data)?;
+            if let Some(extracted_data) = &extracted_data {
+                common::check_entry(args, entry, extracted_data);
+            }
 
-        common::create_output_dir(output)?;
+            progress.inc(1);
+            Ok::<_, CliError>((entry.name_hash, extracted_data))
+        };
 
         #[cfg(not(feature = "rayon"))]
-        let results = sharc
-            .entries
-            .iter()
-            .map(|entry| {
-                let mut local_reader = std::io::Cursor::new(&data);
-                let extracted_data = sharc
-                    .entry_data(&mut local_reader, entry)
-                    .expect("Failed to process entry");
-
-                (entry.name_hash.to_string(), extracted_data)
-            })
-            .collect::<Vec<_>>();
+        let results: Vec<(AfsHash, Option<Vec<u8>>)> =
+            common::with_threads(args.jobs, "--jobs", || {
+                entries
+                    .iter()
+                    .map(|&(_, entry)| decode_entry(entry))
+                    .collect::<Result<Vec<_>, _>>()
+            })??;
 
         #[cfg(feature = "rayon")]
-        let results: Vec<(String, Vec<u8>)> = sharc
-            .entries
-            .par_iter()
-            .map(|entry| {
-                // Each thread gets its own view of the data
-                let mut local_reader = std::io::Cursor::new(&data);
-
-                let extracted_data = sharc
-                    .entry_data(&mut local_reader, entry)
-                    .expect("Failed to process entry");
-
-                (entry.name_hash.to_string(), extracted_data)
-            })
-            .collect();
+        let results: Vec<(AfsHash, Option<Vec<u8>>)> =
+            common::with_threads(args.jobs, "--jobs", || {
+                common::report_parallelism();
 
-        for (name_hash, extracted_data) in results {
-            let output_file = output.join(name_hash);
-            std::fs::write(&output_file, extracted_data)
-                .map_err(|e| format!("failed to write output file {}: {e}", output_file.display()))
-                .unwrap();
-        }
+                entries
+                    .par_iter()
+                    .map(|&(_, entry)| decode_entry(entry))
+                    .collect::<Result<Vec<_>, _>>()
+            })??;
+
+        progress.finish_and_clear();
 
         let time = sharc.archive_data.timestamp;
+        let times = common::EntryTimes::load(args, time)?;
+        let overwrite = args.overwrite_policy();
+
+        // Archives created with `--embed-names` carry their original file names
+        let names_hash = common::hash_path(common::EMBEDDED_NAMES_PATH);
+        let embedded = results
+            .iter()
+            .find(|(name_hash, _)| name_hash.0 == names_hash.0)
+            .and_then(|(_, data)| data.as_deref())
+            .map(common::decode_name_list);
+        let namer = common::OutputNamer::new(args, "", embedded)?;
+
+        let mut failed = Vec::new();
+        for (&(index, _), (name_hash, extracted_data)) in entries.iter().zip(results) {
+            let Some(extracted_data) = extracted_data else {
+                failed.push(name_hash);
+                continue;
+            };
+            if namer.skips(name_hash) {
+                continue;
+            }
+
+            let output_file = namer.path(index, name_hash);
+            common::write_extracted_file(
+                &output_file,
+                &extracted_data,
+                times.get(name_hash),
+                overwrite,
+            )?;
+            common::extract_nested(args, &output_file, &extracted_data)?;
+        }
+        namer.report();
+
         let time_path = output.join(".time");
 
         // Always write the timestamp in big-endian for consistency
         std::fs::write(&time_path, time.to_be_bytes())
-            .map_err(|e| format!("failed to write .time file: {e}"))?;
+            .map_err(|e| CliError::io("failed to write .time file", e))?;
+        common::check_failed_entries(entries.len(), failed)?;
+
+        common::print_result(
+            &format!("Extracted {} files to {}", entries.len(), output.display()),
+            &serde_json::json!({ "output": output, "entries": entries.len() }),
+        );
+        Ok(())
+    }
+
+    pub fn inspect(input: &Path) -> Result<(), CliError> {
+        summary::record_input(input);
+
+        let data = common::read_archive(input)?;
+        let (sharc, _) = common::read_sharc(&data, SHARC_DEFAULT_KEY)?;
+        summary::record_entries(sharc.entries.len(), 0);
+
+        common::print_archive_header(&data, sharc.archive_data.timestamp, &sharc.entries);
+        Ok(())
+    }
+
+    pub fn remove(args: &SharcRemoveArgs) -> Result<(), CliError> {
+        let (args, key) = (
+            &args.remove,
+            keys::resolve_or(args.key.as_ref(), SHARC_DEFAULT_KEY)?,
+        );
+        summary::record_input(&args.io.input);
+
+        let data = common::read_archive(&args.io.input)?;
+        let (sharc, endian) = common::read_sharc(&data, key)?;
+
+        let present: Vec<_> = sharc.entries.iter().map(|entry| entry.name_hash).collect();
+        let selected = common::select_entries(
+            &present,
+            &args.hashes,
+            &args.patterns,
+            args.names.as_deref(),
+        )?;
+
+        if selected.is_empty() {
+            return Err(CliError::InvalidArgument(
+                "no entries matched; archive left unchanged".to_string(),
+            ));
+        }
+
+        let mut archive_writer =
+            SharcBuilder::new(key, SHARC_FILES_KEY).with_timestamp(sharc.archive_data.timestamp);
+
+        for entry in &sharc.entries {
+            if selected.contains(&entry.name_hash.0) {
+                common::log_info(&format!("Removing entry: {}", entry.name_hash));
+                continue;
+            }
+
+            let mut reader = std::io::Cursor::new(&data);
+            let entry_data = sharc.entry_data(&mut reader, entry).map_err(|e| {
+                CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+            })?;
+
+            // Keep each remaining entry's original compression type
+            let compression = entry.location.1;
+            let iv = common::entry_iv(entry.name_hash, false);
+            let compressed = archive_writer
+                .compress_data(&entry_data, compression, &iv)
+                .map_err(|e| {
+                    CliError::Decode(format!("failed to compress entry {}: {e}", entry.name_hash))
+                })?;
+
+            summary::record_entries(1, entry_data.len() as u64);
+            archive_writer.add_compressed_entry(
+                entry.name_hash,
+                compressed,
+                entry_data.len() as u32,
+                compression,
+                iv,
+            );
+        }
 
-        println!(
-            "Extracted {} files to {}",
-            sharc.entries.len(),
-            output.display()
+        let mut output_file = common::create_output_file(&args.io.output)?;
+
+        archive_writer
+            .build(&mut output_file, endian)
+            .map_err(|e| format!("failed to finalize SHARC: {e}"))?;
+
+        output_file
+            .flush()
+            .map_err(|e| CliError::io("failed to flush output file", e))?;
+
+        common::print_result(
+            &format!(
+                "Removed {} of {} entries, wrote {}",
+                selected.len(),
+                sharc.entries.len(),
+                args.io.output.display()
+            ),
+            &serde_json::json!({ "output": args.io.output, "removed": selected.len() }),
+        );
+        Ok(())
+    }
+
+    pub fn append(args: &SharcAppendArgs) -> Result<(), CliError> {
+        let (append, key) = (
+            &args.append,
+            keys::resolve_or(args.key.as_ref(), SHARC_DEFAULT_KEY)?,
+        );
+        summary::record_input(&append.io.input);
+
+        let data = common::read_archive(&append.io.input)?;
+        let (sharc, endian) = common::read_sharc(&data, key)?;
+        let entries = common::plan_append(append, &sharc.entries)?;
+
+        let mut archive_writer =
+            SharcBuilder::new(key, SHARC_FILES_KEY).with_timestamp(sharc.archive_data.timestamp);
+
+        for entry in &entries {
+            let name_hash = entry.name_hash();
+            let (entry_data, compression) = match entry {
+                AppendEntry::Existing(entry) => {
+                    let mut reader = std::io::Cursor::new(&data);
+                    let entry_data = sharc.entry_data(&mut reader, entry).map_err(|e| {
+                        CliError::Decode(format!("failed to read entry {}: {e}", entry.name_hash))
+                    })?;
+
+                    // Keep each existing entry's original compression type
+                    (SmallVec::from_vec(entry_data), entry.location.1)
+                }
+                AppendEntry::New { path, rel_path, .. } => {
+                    let entry_data = common::read_file_bytes(path).map_err(|e| {
+                        CliError::io(format!("failed to read file {}", path.display()), e)
+                    })?;
+                    common::log_entry(&format!("Adding file: {}", rel_path.display()));
+                    (entry_data, append.compression.pick(rel_path))
+                }
+            };
+
+            let iv = common::entry_iv(name_hash, false);
+            let compressed = archive_writer
+                .compress_data(&entry_data, compression, &iv)
+                .map_err(|e| {
+                    CliError::Decode(format!("failed to compress entry {name_hash}: {e}"))
+                })?;
+
+            summary::record_entries(1, entry_data.len() as u64);
+            archive_writer.add_compressed_entry(
+                name_hash,
+                compressed,
+                entry_data.len() as u32,
+                compression,
+                iv,
+            );
+        }
+
+        let mut output_file = common::create_output_file(&append.io.output)?;
+
+        archive_writer
+            .build(&mut output_file, endian)
+            .map_err(|e| format!("failed to finalize SHARC: {e}"))?;
+
+        output_file
+            .flush()
+            .map_err(|e| CliError::io("failed to flush output file", e))?;
+
+        common::print_result(
+            &format!(
+                "Wrote {} entries to {}",
+                entries.len(),
+                append.io.output.display()
+            ),
+            &serde_json::json!({ "output": append.io.output, "entries": entries.len() }),
         );
         Ok(())
     }
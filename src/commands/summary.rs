@@ -0,0 +1,99 @@
+//! A structured summary of each run, printed as JSON with `--summary-json`.
+//!
+//! Commands record what they read and wrote as they go; `main` prints the
+//! summary once the command has finished.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::error::CliError;
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub command: String,
+    pub inputs: Vec<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    /// Number of entries / files processed
+    pub entries: usize,
+    /// Total size of the processed entries, in bytes
+    pub bytes: u64,
+    pub duration_secs: f64,
+    pub failures: Vec<String>,
+    /// Exit code of the first error
+    #[serde(skip)]
+    pub exit_code: Option<i32>,
+}
+
+static SUMMARY: Mutex<Summary> = Mutex::new(Summary {
+    command: String::new(),
+    inputs: Vec::new(),
+    outputs: Vec::new(),
+    entries: 0,
+    bytes: 0,
+    duration_secs: 0.0,
+    failures: Vec::new(),
+    exit_code: None,
+});
+
+fn summary() -> MutexGuard<'static, Summary> {
+    SUMMARY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records a file or folder the command read from.
+pub fn record_input(path: &Path) {
+    summary().inputs.push(path.to_path_buf());
+}
+
+/// Records a file or folder the command wrote to.
+pub fn record_output(path: &Path) {
+    summary().outputs.push(path.to_path_buf());
+}
+
+/// Records `count` processed entries, totalling `bytes` bytes.
+pub fn record_entries(count: usize, bytes: u64) {
+    let mut summary = summary();
+    summary.entries += count;
+    summary.bytes += bytes;
+}
+
+/// Number of entries and total bytes recorded so far.
+pub fn totals() -> (usize, u64) {
+    let summary = summary();
+    (summary.entries, summary.bytes)
+}
+
+/// Records an error that made the command fail, and the exit code it calls for.
+pub fn record_failure(error: &str, exit_code: i32) {
+    let mut summary = summary();
+    summary.failures.push(error.to_string());
+    summary.exit_code.get_or_insert(exit_code);
+}
+
+/// The exit code of the first recorded error, if there was one.
+pub fn exit_code() -> Option<i32> {
+    summary().exit_code
+}
+
+/// Prints the summary as a single JSON object to `path`, or to stdout if `path` is `-`.
+pub fn write(path: &Path, command: String, duration: Duration) -> Result<(), CliError> {
+    let json = {
+        let mut summary = summary();
+        summary.command = command;
+        summary.duration_secs = duration.as_secs_f64();
+
+        serde_json::to_string(&*summary).map_err(|e| format!("failed to serialize summary: {e}"))?
+    };
+
+    if path.as_os_str() == "-" {
+        println!("{json}");
+        return Ok(());
+    }
+
+    std::fs::File::create(path)
+        .and_then(|mut file| writeln!(file, "{json}"))
+        .map_err(|e| CliError::io(format!("failed to write summary {}", path.display()), e))
+}
@@ -0,0 +1,153 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use clap::Args;
+use hdk_archive::structs::Entry;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::commands::{Execute, common, summary};
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub struct Verify {
+    /// SHARC / BAR archive to verify, optionally wrapped in SDAT
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// Folder of source files to compare each entry against, matched by name hash
+    #[clap(long, value_name = "DIR")]
+    pub against: Option<PathBuf>,
+}
+
+impl Execute for Verify {
+    fn execute(self) -> Result<(), CliError> {
+        self.verify()
+    }
+}
+
+impl Verify {
+    fn verify(&self) -> Result<(), CliError> {
+        summary::record_input(&self.input);
+
+        let archive = common::OpenArchive::open(&self.input)?;
+        let entries = archive.entries();
+
+        let sources: Option<HashMap<i32, PathBuf>> = match &self.against {
+            Some(dir) => {
+                summary::record_input(dir);
+                if !dir.is_dir() {
+                    return Err(CliError::InvalidArgument(format!(
+                        "{} is not a folder",
+                        dir.display()
+                    )));
+                }
+
+                let files = common::collect_input_files(dir)?;
+                let present: HashSet<i32> = entries.iter().map(|e| e.name_hash.0).collect();
+                let unused = files
+                    .iter()
+                    .filter(|(_, _, hash)| !present.contains(&hash.0))
+                    .count();
+                if unused > 0 {
+                    common::log_info(&format!(
+                        "Warning: {unused} source files have no matching archive entry"
+                    ));
+                }
+
+                Some(
+                    files
+                        .into_iter()
+                        .map(|(abs_path, _, hash)| (hash.0, abs_path))
+                        .collect(),
+                )
+            }
+            None => None,
+        };
+
+        let progress = common::progress_bar(entries.len());
+
+        // Every entry is checked, so one bad entry doesn't hide the others
+        let verify_entry = |entry: &Entry| {
+            let result = Self::verify_entry(&archive, entry, sources.as_ref());
+            progress.inc(1);
+            result.err().map(|e| e.to_string())
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let failures: Vec<String> = entries.iter().filter_map(verify_entry).collect();
+
+        #[cfg(feature = "rayon")]
+        let failures: Vec<String> = entries.par_iter().filter_map(verify_entry).collect();
+
+        progress.finish_and_clear();
+
+        for failure in &failures {
+            println!("FAIL {failure}");
+        }
+
+        let bytes = entries.iter().map(|e| u64::from(e.uncompressed_size)).sum();
+        summary::record_entries(entries.len(), bytes);
+
+        if !failures.is_empty() {
+            return Err(CliError::Decode(format!(
+                "{} of {} entries failed verification",
+                failures.len(),
+                entries.len()
+            )));
+        }
+
+        common::print_result(
+            &format!("All {} entries verified", entries.len()),
+            &serde_json::json!({ "entries": entries.len(), "failures": 0 }),
+        );
+        Ok(())
+    }
+
+    /// Decodes an entry and, if `sources` is given, compares it against its source file.
+    fn verify_entry(
+        archive: &common::OpenArchive,
+        entry: &Entry,
+        sources: Option<&HashMap<i32, PathBuf>>,
+    ) -> Result<(), CliError> {
+        let data = common::timed(
+            || format!("Verifying {}", entry.name_hash),
+            || archive.entry_data(entry),
+        )?;
+
+        if data.len() != entry.uncompressed_size as usize {
+            return Err(CliError::Decode(format!(
+                "{}: decoded to {} bytes, expected {}",
+                entry.name_hash,
+                data.len(),
+                entry.uncompressed_size
+            )));
+        }
+
+        let Some(sources) = sources else {
+            return Ok(());
+        };
+
+        let source = sources
+            .get(&entry.name_hash.0)
+            .ok_or_else(|| format!("{}: no matching source file", entry.name_hash))?;
+        let expected = common::read_file_bytes(source).map_err(|e| {
+            format!(
+                "{}: failed to read {}: {e}",
+                entry.name_hash,
+                source.display()
+            )
+        })?;
+
+        if *data != *expected {
+            return Err(CliError::Decode(format!(
+                "{}: differs from {}",
+                entry.name_hash,
+                source.display()
+            )));
+        }
+
+        Ok(())
+    }
+}
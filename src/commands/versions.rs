@@ -0,0 +1,20 @@
+use clap::Args;
+
+use crate::commands::Execute;
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub struct Versions {}
+
+impl Execute for Versions {
+    fn execute(self) -> Result<(), CliError> {
+        // The library versions are captured from `Cargo.lock` by `build.rs`
+        println!("hdk-cli {}", env!("CARGO_PKG_VERSION"));
+        println!("hdk-archive {}", env!("HDK_ARCHIVE_VERSION"));
+        println!("hdk-comp {}", env!("HDK_COMP_VERSION"));
+        println!("hdk-firmware {}", env!("HDK_FIRMWARE_VERSION"));
+        println!("hdk-sdat {}", env!("HDK_SDAT_VERSION"));
+        println!("hdk-secure {}", env!("HDK_SECURE_VERSION"));
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! The error type returned by commands.
+//!
+//! Each kind of failure maps to its own process exit code, so scripts can tell
+//! e.g. a missing file apart from a corrupt archive.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    /// A file or folder couldn't be read or written
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// An archive couldn't be opened or parsed
+    #[error("{0}")]
+    Archive(String),
+
+    /// Data couldn't be decompressed, decrypted or decoded
+    #[error("{0}")]
+    Decode(String),
+
+    /// The user declined a confirmation prompt
+    #[error("{0}")]
+    UserDeclined(String),
+
+    /// An argument or input file was invalid
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    /// Any other failure
+    #[error("{0}")]
+    Other(String),
+}
+
+impl CliError {
+    /// An I/O error, with `context` describing what was being done.
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// The process exit code for this kind of error.
+    ///
+    /// 2 is what clap exits with for invalid arguments, so it's shared with them.
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::Other(_) => 1,
+            Self::InvalidArgument(_) => 2,
+            Self::Io { .. } => 3,
+            Self::Archive(_) => 4,
+            Self::Decode(_) => 5,
+            Self::UserDeclined(_) => 6,
+        }
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
+}
+
+impl From<&str> for CliError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.to_string())
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(source: std::io::Error) -> Self {
+        Self::io("I/O error", source)
+    }
+}
+
+impl From<binrw::Error> for CliError {
+    fn from(source: binrw::Error) -> Self {
+        Self::Archive(source.to_string())
+    }
+}
+
+impl From<dialoguer::Error> for CliError {
+    fn from(source: dialoguer::Error) -> Self {
+        Self::Other(format!("failed to read user input: {source}"))
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(source: serde_json::Error) -> Self {
+        Self::Other(source.to_string())
+    }
+}
@@ -1,3 +1,14 @@
+//! Built-in keys, and the keystore that `--key @name` is resolved against.
+//!
+//! A `--keyset` file adds named keys on top of the built-in ones, so keys can be
+//! kept out of shell history and reused across runs.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::error::CliError;
+
 /// Encrypts the header and the entries.
 /// Used in core SHARC archives.
 pub const SHARC_DEFAULT_KEY: [u8; 32] = [
@@ -34,3 +45,165 @@ pub const BLOWFISH_DEFAULT_KEY: [u8; 32] = [
     0x80, 0x6d, 0x79, 0x16, 0x23, 0x42, 0xa1, 0x0e, 0x8f, 0x78, 0x14, 0xd4, 0xf9, 0x94, 0xa2, 0xd1,
     0x74, 0x13, 0xfc, 0xa8, 0xf6, 0xe0, 0xb8, 0xa4, 0xed, 0xb9, 0xdc, 0x32, 0x7f, 0x8b, 0xa7, 0x11,
 ];
+
+/// Built-in keys that `--key @name` can refer to.
+pub const BUILTIN_KEYS: &[(&str, [u8; 32])] = &[
+    ("sharc", SHARC_DEFAULT_KEY),
+    ("sharc-sdat", SHARC_SDAT_KEY),
+    ("bar", BAR_DEFAULT_KEY),
+    ("bar-signature", BAR_SIGNATURE_KEY),
+    ("blowfish", BLOWFISH_DEFAULT_KEY),
+];
+
+/// Keys loaded from the `--keyset` file, by name.
+static KEYSET: OnceLock<HashMap<String, [u8; 32]>> = OnceLock::new();
+
+/// A `--key` value.
+///
+/// `@` references are only resolved when the key is used, since the keyset isn't
+/// loaded yet while the arguments are being parsed.
+#[derive(Debug, Clone)]
+pub enum KeyArg {
+    /// A key given as 64 hex digits
+    Literal([u8; 32]),
+    /// `@name` of a key in the keyset or the built-ins, or `@path` to a key file
+    Reference(String),
+}
+
+impl KeyArg {
+    /// Returns the key bytes, looking up named keys before key files.
+    pub fn resolve(&self) -> Result<[u8; 32], CliError> {
+        match self {
+            Self::Literal(key) => Ok(*key),
+            Self::Reference(reference) => {
+                named_key(reference).map_or_else(|| read_key_file(Path::new(reference)), Ok)
+            }
+        }
+    }
+}
+
+/// Resolves an optional `--key`, falling back to `default` if none was given.
+pub fn resolve_or(key: Option<&KeyArg>, default: [u8; 32]) -> Result<[u8; 32], CliError> {
+    key.map_or(Ok(default), KeyArg::resolve)
+}
+
+/// Parses a `--key` value: 64 hex digits, or `@name` / `@path` to resolve later.
+///
+/// This is the clap value parser for every `--key` argument.
+pub fn parse_key(value: &str) -> Result<KeyArg, CliError> {
+    value.strip_prefix('@').map_or_else(
+        || parse_hex_key(value).map(KeyArg::Literal),
+        |reference| Ok(KeyArg::Reference(reference.to_string())),
+    )
+}
+
+/// Loads the named keys of a keyset file.
+///
+/// `.json` files hold an object of names to hex keys. Anything else is read as a
+/// flat TOML table of `name = "hex"` lines.
+pub fn load_keyset(path: &Path) -> Result<(), CliError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| CliError::io(format!("failed to read keyset {}", path.display()), e))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let entries: Vec<(String, String)> = if is_json {
+        serde_json::from_str::<HashMap<String, String>>(&text)
+            .map_err(|e| {
+                CliError::InvalidArgument(format!("invalid keyset {}: {e}", path.display()))
+            })?
+            .into_iter()
+            .collect()
+    } else {
+        parse_toml_keys(&text).map_err(|e| {
+            CliError::InvalidArgument(format!("invalid keyset {}: {e}", path.display()))
+        })?
+    };
+
+    let keys = entries
+        .into_iter()
+        .map(|(name, hex)| {
+            let key = parse_hex_key(&hex).map_err(|e| {
+                CliError::InvalidArgument(format!(
+                    "invalid key `{name}` in keyset {}: {e}",
+                    path.display()
+                ))
+            })?;
+            Ok((name, key))
+        })
+        .collect::<Result<HashMap<_, _>, CliError>>()?;
+
+    let _ = KEYSET.set(keys);
+    Ok(())
+}
+
+/// Looks a key up by name, in the keyset first and then the built-ins.
+fn named_key(name: &str) -> Option<[u8; 32]> {
+    KEYSET
+        .get()
+        .and_then(|keyset| keyset.get(name).copied())
+        .or_else(|| {
+            BUILTIN_KEYS
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map(|(_, key)| *key)
+        })
+}
+
+/// Reads a key file holding either the 32 raw key bytes or their hex encoding.
+fn read_key_file(path: &Path) -> Result<[u8; 32], CliError> {
+    let data = std::fs::read(path).map_err(|e| {
+        CliError::io(
+            format!(
+                "`@{}` is not a known key name, and failed to read it as a key file",
+                path.display()
+            ),
+            e,
+        )
+    })?;
+
+    if let Ok(key) = <[u8; 32]>::try_from(data.as_slice()) {
+        return Ok(key);
+    }
+
+    parse_hex_key(&String::from_utf8_lossy(&data))
+        .map_err(|e| CliError::InvalidArgument(format!("invalid key file {}: {e}", path.display())))
+}
+
+/// Parses a key given as 64 hex digits.
+fn parse_hex_key(value: &str) -> Result<[u8; 32], CliError> {
+    let bytes = hex::decode(value.trim())
+        .map_err(|e| CliError::InvalidArgument(format!("invalid hex key: {e}")))?;
+
+    bytes.try_into().map_err(|b: Vec<u8>| {
+        CliError::InvalidArgument(format!("key must be 32 bytes, got {}", b.len()))
+    })
+}
+
+/// Parses the `name = "hex"` lines of a flat TOML table, skipping blank lines and comments.
+fn parse_toml_keys(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = line.split_once('=').and_then(|(name, value)| {
+            let name = name.trim().trim_matches('"');
+            let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+            (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+        });
+
+        match parsed {
+            Some(entry) => entries.push(entry),
+            None => {
+                return Err(format!("line {}: expected `name = \"hex\"`", number + 1));
+            }
+        }
+    }
+
+    Ok(entries)
+}
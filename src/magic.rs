@@ -2,14 +2,20 @@
 //! crate, allowing us to check for magic bytes that only exist
 //! in the context of PlayStation Home development.
 
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
 use hdk_archive::structs::{ArchiveVersion, Endianness};
 
 /// Convenience function to convert a magic value to an Endianness enum.
-pub const fn magic_to_endianess(buf: &[u8; 4]) -> Endianness {
+///
+/// Returns `None` if the value isn't the archive magic in either byte order.
+pub const fn magic_to_endianess(buf: &[u8; 4]) -> Option<Endianness> {
     match buf {
-        b"\xE1\x17\xEF\xAD" => Endianness::Little,
-        b"\xAD\xEF\x17\xE1" => Endianness::Big,
-        _ => panic!("Invalid magic value"),
+        b"\xE1\x17\xEF\xAD" => Some(Endianness::Little),
+        b"\xAD\xEF\x17\xE1" => Some(Endianness::Big),
+        _ => None,
     }
 }
 
@@ -28,28 +34,30 @@ fn archive_matcher(buf: &[u8]) -> bool {
 }
 
 /// Convenience function to extract the archive version from the header bytes, if it matches the archive magic.
-fn extract_version(buf: &[u8]) -> Option<ArchiveVersion> {
-    if buf.len() < 8 {
-        return None;
-    }
+pub fn extract_version(buf: &[u8]) -> Option<ArchiveVersion> {
+    let version: u16 = (version_and_flags(buf)? >> 16) as u16;
+    ArchiveVersion::try_from(version).ok()
+}
 
-    use hdk_archive::structs::ARCHIVE_MAGIC;
+/// Convenience function to extract the archive flags from the header bytes, if it matches the archive magic.
+///
+/// The flags share a header word with the version, and take up its low 16 bits.
+pub fn extract_flags(buf: &[u8]) -> Option<u16> {
+    Some(version_and_flags(buf)? as u16)
+}
 
-    let endianess = if buf[0..4] == ARCHIVE_MAGIC.to_le_bytes() {
-        Endianness::Little
-    } else if buf[0..4] == ARCHIVE_MAGIC.to_be_bytes() {
-        Endianness::Big
-    } else {
+/// Reads the header word holding the archive version and flags, in the byte order given by the magic.
+fn version_and_flags(buf: &[u8]) -> Option<u32> {
+    if buf.len() < 8 {
         return None;
-    };
+    }
 
-    let version_and_flags: u32 = match endianess {
+    let version_and_flags: u32 = match magic_to_endianess(buf[0..4].try_into().unwrap())? {
         Endianness::Little => u32::from_le_bytes(buf[4..8].try_into().unwrap()),
         Endianness::Big => u32::from_be_bytes(buf[4..8].try_into().unwrap()),
     };
 
-    let version: u16 = (version_and_flags >> 16) as u16;
-    ArchiveVersion::try_from(version).ok()
+    Some(version_and_flags)
 }
 
 /// SHARC archive matcher based on the magic value in the header.
@@ -64,7 +72,7 @@ fn sharc_matcher(buf: &[u8]) -> bool {
         return false;
     }
 
-    if let Some(version) = extract_version(magic) {
+    if let Some(version) = extract_version(buf) {
         return version == ArchiveVersion::SHARC;
     }
 
@@ -83,7 +91,7 @@ fn bar_matcher(buf: &[u8]) -> bool {
         return false;
     }
 
-    if let Some(version) = extract_version(magic) {
+    if let Some(version) = extract_version(buf) {
         return version == ArchiveVersion::BAR;
     }
 
@@ -100,7 +108,7 @@ fn edge_lzma_matcher(buf: &[u8]) -> bool {
 }
 
 /// SDAT container matcher
-fn sdat_matcher(buf: &[u8]) -> bool {
+pub fn sdat_matcher(buf: &[u8]) -> bool {
     // SDAT files have "NPD" at the start and "SDATA" within the last 32 bytes.
     if buf.len() < 36 {
         return false;
@@ -117,6 +125,38 @@ fn sdat_matcher(buf: &[u8]) -> bool {
     magic_start && magic_end
 }
 
+/// Number of bytes from the start of a file that [`sample_file`] reads.
+///
+/// This comfortably covers the headers of every matcher `infer` ships with.
+pub const SAMPLE_HEADER_LEN: u64 = 8192;
+
+/// Number of bytes from the end of a file that [`sample_file`] reads, for [`sdat_matcher`].
+pub const SAMPLE_TAIL_LEN: u64 = 32;
+
+/// Reads just enough of a file for the matchers to identify it.
+///
+/// Files longer than [`SAMPLE_HEADER_LEN`] + [`SAMPLE_TAIL_LEN`] bytes are sampled as
+/// their first [`SAMPLE_HEADER_LEN`] bytes followed by their last [`SAMPLE_TAIL_LEN`]
+/// bytes, so huge files never have to be loaded whole.
+pub fn sample_file(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    if len <= SAMPLE_HEADER_LEN + SAMPLE_TAIL_LEN {
+        let mut sample = Vec::with_capacity(len as usize);
+        file.read_to_end(&mut sample)?;
+        return Ok(sample);
+    }
+
+    let mut sample = vec![0u8; (SAMPLE_HEADER_LEN + SAMPLE_TAIL_LEN) as usize];
+    let (header, tail) = sample.split_at_mut(SAMPLE_HEADER_LEN as usize);
+    file.read_exact(header)?;
+    file.seek(SeekFrom::End(-(SAMPLE_TAIL_LEN as i64)))?;
+    file.read_exact(tail)?;
+
+    Ok(sample)
+}
+
 // Type alias to represent MIME types
 pub type MimeType = (&'static str, &'static str);
 
@@ -1,6 +1,8 @@
 use clap::Parser;
+use std::time::Instant;
 
 mod commands;
+mod error;
 mod keys;
 mod magic;
 
@@ -8,5 +10,42 @@ use crate::commands::Execute;
 
 fn main() {
     let args = commands::Main::parse();
-    args.command.execute();
+    commands::set_globals(args.global);
+
+    let command_line: Vec<String> = std::env::args().collect();
+    commands::log_to_file(&format!("Running: {}", command_line.join(" ")));
+
+    let start = Instant::now();
+    let result = commands::common::init_thread_pool()
+        .and_then(|()| {
+            commands::globals()
+                .keyset
+                .as_deref()
+                .map_or(Ok(()), keys::load_keyset)
+        })
+        .and_then(|()| args.command.execute());
+    if let Err(e) = result {
+        commands::report_error(&e);
+    }
+
+    commands::log_to_file("Finished");
+
+    if let Some(path) = &commands::globals().summary_json {
+        // The sub-command names come before the first flag, e.g. `sharc extract`
+        let command = command_line
+            .iter()
+            .skip(1)
+            .take_while(|arg| !arg.starts_with('-'))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if let Err(e) = commands::summary::write(path, command, start.elapsed()) {
+            eprintln!("Error: {e}");
+        }
+    }
+
+    if let Some(code) = commands::summary::exit_code() {
+        std::process::exit(code);
+    }
 }